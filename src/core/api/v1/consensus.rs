@@ -1,6 +1,13 @@
 use ed25519_dalek::SigningKey;
 use crate::core::canister::canister::{Canister, CanisterFunctionPayload};
+use crate::core::consensus::epoch::{self, EpochValidatorSet};
+use crate::core::consensus::finality::{self, FinalityCertificate};
+use crate::core::consensus::liveness;
+use crate::core::consensus::validator::ValidatorInfo;
+use crate::core::consensus::ledger_tree::{self, AccountProof, FinalizedAccountProof};
+use crate::core::consensus::merkle::{self, TransactionProof};
 use crate::core::consensus::model::ConsensusEngine;
+use crate::core::types::{BlockHeader, Transaction, Unjail};
 
 pub fn produce_block(
     canister: &mut Canister,
@@ -20,4 +27,63 @@ pub fn select_next_validator(
     canister.execute_function(CanisterFunctionPayload::SelectValidator {
         consensus_engine,
     })
-}
\ No newline at end of file
+}
+
+pub fn tip_height(consensus_engine: &ConsensusEngine) -> Option<u64> {
+    let chain = consensus_engine.chain.lock();
+    chain.last().map(|block| block.header.height)
+}
+
+pub fn finalized_height(consensus_engine: &ConsensusEngine) -> Option<u64> {
+    finality::finalized_height(consensus_engine)
+}
+
+pub fn get_certificate(consensus_engine: &ConsensusEngine, height: u64) -> Option<FinalityCertificate> {
+    finality::get_certificate(consensus_engine, height)
+}
+
+/// Epoch the next block belongs to.
+pub fn current_epoch(consensus_engine: &ConsensusEngine) -> u64 {
+    let next_height = tip_height(consensus_engine).map(|height| height + 1).unwrap_or(0);
+    epoch::epoch_of(consensus_engine, next_height)
+}
+
+/// Validators allowed to sign blocks in `epoch`, or `None` for an epoch whose
+/// set has not been snapshotted.
+pub fn get_validator_set(consensus_engine: &ConsensusEngine, epoch: u64) -> Option<EpochValidatorSet> {
+    epoch::get_validator_set(consensus_engine, epoch)
+}
+
+/// Produced and missed slot counters and jail status of a validator.
+pub fn get_validator_liveness(consensus_engine: &ConsensusEngine, address: &str) -> Option<ValidatorInfo> {
+    liveness::get_liveness(consensus_engine, address)
+}
+
+/// Signs an unjail request for this node's validator and queues it locally.
+/// `Network::submit_unjail` gossips it to the rest of the subnet.
+pub fn unjail(consensus_engine: &mut ConsensusEngine, signing_key: &SigningKey) -> Result<Unjail, String> {
+    let unjail = liveness::sign_unjail(consensus_engine, signing_key)?;
+    liveness::add_unjail(consensus_engine, unjail.clone())?;
+    Ok(unjail)
+}
+
+pub fn get_account_proof(consensus_engine: &ConsensusEngine, address: &str) -> Result<FinalizedAccountProof, String> {
+    ledger_tree::prove_finalized_account(consensus_engine, address)
+}
+
+pub fn verify_account_proof(ledger_root: &[u8; 32], proof: &AccountProof) -> bool {
+    ledger_tree::verify_account_proof(ledger_root, proof)
+}
+
+/// Inclusion proof for `tx_hash` in the canonical block at `height`.
+pub fn get_transaction_proof(consensus_engine: &ConsensusEngine, height: u64, tx_hash: &str) -> Result<TransactionProof, String> {
+    let block = {
+        let chain = consensus_engine.chain.lock();
+        chain.get(height as usize).cloned().ok_or_else(|| format!("No block at height {}", height))?
+    };
+    merkle::prove_transaction(&block, tx_hash)
+}
+
+pub fn verify_transaction_proof(header: &BlockHeader, tx: &Transaction, proof: &TransactionProof) -> bool {
+    merkle::verify_transaction_proof(header, tx, proof)
+}
@@ -3,4 +3,6 @@ pub mod transaction;
 pub mod staking;
 pub mod governance;
 pub mod consensus;
-pub mod nervous;
\ No newline at end of file
+pub mod nervous;
+pub mod network;
+pub mod subnet;
@@ -0,0 +1,9 @@
+use crate::core::network::{Ban, PeerReputation};
+
+pub fn list_bans(reputation: &PeerReputation) -> Vec<Ban> {
+    reputation.list_bans()
+}
+
+pub fn clear_ban(reputation: &PeerReputation, ip: &str) -> bool {
+    reputation.clear_ban(ip)
+}
@@ -0,0 +1,9 @@
+use crate::core::subnet::{Subnet, SubnetRegistry};
+
+pub fn list_subnets(subnet_registry: &SubnetRegistry) -> Vec<Subnet> {
+    subnet_registry.list_subnets()
+}
+
+pub fn get_subnet(subnet_registry: &SubnetRegistry, subnet_id: &str) -> Option<Subnet> {
+    subnet_registry.get_subnet(subnet_id)
+}
@@ -1,5 +1,7 @@
 use crate::core::types::{Transaction, TransactionType, TransactionStatus, Address};
 use crate::core::consensus::{compute_transaction_hash};
+use crate::core::consensus::transaction::{self as consensus_transaction, next_nonce};
+use crate::core::consensus::execution::{self, TransactionResult};
 use crate::core::crypto::sign_data;
 use crate::core::canister::canister::{Canister, CanisterFunctionPayload};
 use crate::core::consensus::model::ConsensusEngine;
@@ -16,7 +18,7 @@ pub fn build_transaction(
     nrc_memo: u32,
     tx_type: TransactionType,
 ) -> Transaction {
-    let fee = amount / 100;
+    let fee = (amount / 100).max(consensus.config.min_fee);
 
     let chain_guard = consensus.chain.lock();
     let total_chain_txs: usize = chain_guard.iter().map(|block| block.transactions.len()).sum();
@@ -25,12 +27,16 @@ pub fn build_transaction(
     let mempool_len = mempool_guard.len();
 
     let index = total_chain_txs as u32 + mempool_len as u32;
+    drop(mempool_guard);
+    drop(chain_guard);
+    let nonce = next_nonce(consensus, &from);
 
     Transaction {
         hash: String::new(),
         r#type: tx_type,
         status: TransactionStatus::Pending,
         index,
+        nonce,
         timestamp: Utc::now(),
         from,
         to,
@@ -73,3 +79,13 @@ pub fn submit_transaction(
         tx,
     })
 }
+
+/// Success or failure of a transaction once it has been executed in a block.
+pub fn get_transaction_result(consensus_engine: &ConsensusEngine, tx_hash: &str) -> Option<TransactionResult> {
+    execution::get_transaction_result(consensus_engine, tx_hash)
+}
+
+/// Transactions from `sender` still waiting in the mempool, lowest nonce first.
+pub fn pending_transactions(consensus_engine: &ConsensusEngine, sender: &str) -> Vec<Transaction> {
+    consensus_transaction::pending_transactions(consensus_engine, sender)
+}
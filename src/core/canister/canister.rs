@@ -9,6 +9,7 @@ use crate::core::governance::{
 use crate::core::nervous::*;
 use crate::core::consensus::model::ConsensusEngine;
 use crate::core::consensus::transaction::cancel_transaction;
+use crate::core::xnet::{VerifiedXNetMessage, XNetStreams};
 
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -79,7 +80,17 @@ pub enum CanisterFunctionPayload<'a> {
     CancelTransfer {
         consensus_engine: &'a mut ConsensusEngine,
         tx_hash: String,
-    }
+    },
+    XNetSend {
+        streams: &'a XNetStreams,
+        dst_subnet: String,
+        receiver: String,
+        method: String,
+        payload: Vec<u8>,
+    },
+    XNetCall {
+        message: VerifiedXNetMessage,
+    },
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -88,10 +99,34 @@ pub struct Canister {
     pub canister_id: String,
     pub controller: Address,
     pub module_hash: String,
+    pub subnet_id: String,
     #[serde(skip)]
     pub db: DbWrapper,
 }
 
+/// Canister record as persisted before `subnet_id` was added.
+#[derive(Deserialize)]
+pub(crate) struct LegacyCanister {
+    state: Arc<MutexWrapper<HashMap<String, String>>>,
+    canister_id: String,
+    controller: Address,
+    module_hash: String,
+}
+
+impl From<LegacyCanister> for Canister {
+    fn from(legacy: LegacyCanister) -> Self {
+        Canister {
+            state: legacy.state,
+            canister_id: legacy.canister_id,
+            controller: legacy.controller,
+            module_hash: legacy.module_hash,
+            subnet_id: String::new(),
+            // Replaced by the registry's handle once loaded, as for `#[serde(skip)]`.
+            db: DbWrapper::default(),
+        }
+    }
+}
+
 impl Canister {
     pub fn new(canister_id: String, controller: Address, db: Arc<DB>) -> Self {
         let mut hasher = Sha256::new();
@@ -106,6 +141,7 @@ impl Canister {
             canister_id,
             controller,
             module_hash,
+            subnet_id: String::new(),
             db: DbWrapper(db),
         }
     }
@@ -118,7 +154,7 @@ impl Canister {
             CanisterFunctionPayload::Transfer { consensus_engine, tx } => {
                 add_transaction(consensus_engine, tx.clone())?;
                 Ok(format!(
-                    "Transaction executed: {} -> {} ({} tokens)",
+                    "Transaction queued: {} -> {} ({} tokens)",
                     tx.from, tx.to, tx.amount
                 ))
             }
@@ -186,8 +222,9 @@ impl Canister {
             } => {
                 let block = produce_block(consensus_engine, signing_key)?;
                 Ok(format!(
-                    "Block produced: {} transactions, timestamp: {}",
+                    "Block produced: {} transactions, {} in fees, timestamp: {}",
                     block.transactions.len(),
+                    block.header.total_fees,
                     block.header.timestamp
                 ))
             }
@@ -228,6 +265,36 @@ impl Canister {
                 finalize(governance, proposal_id)?;
                 Ok(format!("Finalized proposal: {}", proposal_id))
             }
+            CanisterFunctionPayload::XNetSend {
+                streams,
+                dst_subnet,
+                receiver,
+                method,
+                payload,
+            } => {
+                let index = streams.enqueue(&dst_subnet, self.canister_id.clone(), receiver.clone(), method, payload)?;
+                Ok(format!(
+                    "XNet message {} queued for canister {} on subnet {}",
+                    index, receiver, dst_subnet
+                ))
+            }
+            CanisterFunctionPayload::XNetCall { message } => {
+                let xnet_message = message.message();
+                if xnet_message.receiver != self.canister_id {
+                    return Err(format!(
+                        "XNet message for {} delivered to canister {}",
+                        xnet_message.receiver, self.canister_id
+                    ));
+                }
+
+                let key = format!("xnet_{}_{}", message.src_subnet(), xnet_message.index);
+                let value = format!("{}:{}:{}", xnet_message.sender, xnet_message.method, hex::encode(&xnet_message.payload));
+                self.store_state(key, value);
+                Ok(format!(
+                    "XNet message {} from {} on subnet {} delivered to {}",
+                    xnet_message.index, xnet_message.sender, message.src_subnet(), self.canister_id
+                ))
+            }
         }
     }
 
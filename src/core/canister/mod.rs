@@ -1,2 +1,3 @@
+#[allow(clippy::module_inception)]
 pub mod canister;
 pub mod registry;
\ No newline at end of file
@@ -1,5 +1,6 @@
-use crate::core::canister::canister::{Canister};
-use crate::core::types::{DbWrapper, MutexWrapper};
+use crate::core::canister::canister::{Canister, LegacyCanister};
+use crate::core::subnet::SubnetRegistry;
+use crate::core::types::{decode_record, DbWrapper, MutexWrapper};
 use std::collections::HashMap;
 use std::sync::Arc;
 use rocksdb::DB;
@@ -8,43 +9,79 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize, Clone)]
 pub struct CanisterRegistry {
     pub canisters: Arc<MutexWrapper<HashMap<String, Arc<MutexWrapper<Canister>>>>>,
+    pub subnet_id: String,
+    #[serde(skip)]
+    pub subnets: Option<SubnetRegistry>,
     #[serde(skip)]
     pub db: DbWrapper,
 }
 
 impl CanisterRegistry {
-    pub fn new(db: Arc<DB>) -> Self {
+    pub fn new(subnet_id: String, subnets: SubnetRegistry, db: Arc<DB>) -> Self {
         let mut registry = HashMap::new();
         let stored_canisters = db.iterator(rocksdb::IteratorMode::Start);
         for item in stored_canisters {
             let (key, value) = item.unwrap();
-            if key.starts_with(b"canister_") {
-                if let Ok(canister) = bincode::deserialize::<Canister>(&value) {
-                    registry.insert(String::from_utf8(key.to_vec()).unwrap(), Arc::new(MutexWrapper::new(canister)));
+            if !key.starts_with(b"canister_") {
+                continue;
+            }
+
+            // Canisters in the local store are hosted by this node, so records
+            // from before subnets existed, or from a previous subnet assignment,
+            // move to the local subnet.
+            let mut canister = match decode_record::<Canister, LegacyCanister>(&db, &key, &value) {
+                Ok(canister) => canister,
+                Err(err) => {
+                    eprintln!("❌ Skipping canister record: {}", err);
+                    continue;
                 }
+            };
+            if canister.subnet_id != subnet_id {
+                canister.subnet_id = subnet_id.clone();
+                db.put(&key, bincode::serialize(&canister).unwrap()).unwrap();
             }
+            subnets.assign_canister(&subnet_id, &canister.canister_id);
+
+            canister.db = DbWrapper(Arc::clone(&db));
+            canister.load_state();
+            registry.insert(canister.canister_id.clone(), Arc::new(MutexWrapper::new(canister)));
         }
         Self {
             canisters: Arc::new(MutexWrapper::new(registry)),
+            subnet_id,
+            subnets: Some(subnets),
             db: DbWrapper(db),
         }
     }
 
-    pub fn register_canister(&mut self, canister_id: &String, canister: Canister) {
+    pub fn register_canister(&mut self, canister_id: &String, mut canister: Canister) {
+        canister.subnet_id = self.subnet_id.clone();
+
         let mut registry = self.canisters.lock();
         let serialized = bincode::serialize(&canister).unwrap();
         let key = format!("canister_{}", canister_id);
         self.db.put(key.as_bytes(), serialized).unwrap();
         registry.insert(canister_id.clone(), Arc::new(MutexWrapper::new(canister)));
+
+        if let Some(subnets) = &self.subnets {
+            subnets.assign_canister(&self.subnet_id, canister_id);
+        }
     }
 
     pub fn get_canister(&self, canister_id: &str) -> Option<Canister> {
         let key = format!("canister_{}", canister_id);
-        if let Ok(Some(data)) = self.db.get(key.as_bytes()) {
-            if let Ok(canister) = bincode::deserialize::<Canister>(&data) {
-                return Some(canister);
-            }
+        if let Ok(Some(data)) = self.db.get(key.as_bytes())
+            && let Ok(canister) = bincode::deserialize::<Canister>(&data)
+            && canister.subnet_id == self.subnet_id
+        {
+            return Some(canister);
         }
         None
     }
+
+    /// Shared handle to a live canister hosted on this subnet.
+    pub fn canister_handle(&self, canister_id: &str) -> Option<Arc<MutexWrapper<Canister>>> {
+        let registry = self.canisters.lock();
+        registry.get(canister_id).cloned()
+    }
 }
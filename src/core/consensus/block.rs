@@ -3,107 +3,241 @@ use crate::core::crypto::{sign_data, verify_data};
 use crate::core::consensus::{crypto_hash};
 use crate::core::consensus::model::ConsensusEngine;
 
-use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::sync::Arc;
 use chrono::Utc;
 use ed25519_dalek::{SigningKey, VerifyingKey};
 use bincode;
 use hex;
-use crate::core::consensus::math::consensus_probability;
+use crate::core::consensus::evidence::{evidence_hash, pending_evidence, validate_block_evidence};
+use crate::core::consensus::execution::{BlockOutcome, get_transaction_result, preview_block};
+use crate::core::consensus::liveness::{pending_unjails, unjail_hash, validate_block_unjails};
+use crate::core::consensus::merkle::merkle_levels;
+use crate::core::consensus::slot::{current_slot, next_slot, slot_start_ms, validate_block_slot};
+use crate::core::consensus::template::{build_block_template, transactions_size};
+use crate::core::consensus::transaction::verify_transaction;
+use crate::core::consensus::tree::insert_block;
+use crate::core::consensus::epoch::validator_set_at;
+use crate::core::consensus::validator::{parent_validators, select_leader};
+
+pub const BLOCK_VERSION: u32 = 1;
 
 pub fn produce_block(
     consensus_engine: &mut ConsensusEngine,
     signing_key: &SigningKey
 ) -> Result<Block, String> {
-    let mut mempool_lock = consensus_engine.mempool.lock();
-    let mut chain_lock = consensus_engine.chain.lock();
+    let import_lock = Arc::clone(&consensus_engine.import_lock);
+    let _guard = import_lock.lock();
+
+    let slot = next_slot(consensus_engine);
+    if slot > current_slot(consensus_engine) {
+        return Err(format!("Slot {} has not started yet", slot));
+    }
+
+    let transactions = build_block_template(consensus_engine).transactions;
+    let evidence = pending_evidence(consensus_engine);
+    let next_height = consensus_engine.chain.lock().last().map(|blk| blk.header.height + 1).unwrap_or(0);
+    let unjails = pending_unjails(consensus_engine, next_height);
+    let mut block = {
+        let chain_lock = consensus_engine.chain.lock();
 
-    let transactions = mempool_lock.drain(..).collect::<Vec<_>>();
-    let merkle_root = compute_merkle_root(&transactions);
+        let merkle_root = compute_merkle_root(&transactions);
 
-    let verifying_key = signing_key.verifying_key();
-    let validator_address = hex::encode(verifying_key.to_bytes());
+        let verifying_key = signing_key.verifying_key();
 
-    let parent_hash = chain_lock.last().map(|blk| hash_block(blk)).unwrap_or([0u8; 32]);
+        let parent_hash = chain_lock.last().map(hash_block).unwrap_or([0u8; 32]);
+        let height = chain_lock.last().map(|blk| blk.header.height + 1).unwrap_or(0);
 
-    let mut header = BlockHeader {
-        parent_hash,
-        merkle_root,
-        timestamp: Utc::now().timestamp() as u64,
-        validator: hex::encode(verifying_key.to_bytes()),
-        signature: vec![],
+        let header = BlockHeader {
+            version: BLOCK_VERSION,
+            subnet_id: consensus_engine.subnet_id.clone(),
+            height,
+            parent_hash,
+            slot,
+            merkle_root,
+            state_root: [0u8; 32],
+            ledger_root: [0u8; 32],
+            tx_count: transactions.len() as u32,
+            total_fees: 0,
+            evidence_hash: evidence_hash(&evidence),
+            unjail_hash: unjail_hash(&unjails),
+            timestamp: Utc::now().timestamp() as u64,
+            validator: hex::encode(verifying_key.to_bytes()),
+            signature: vec![],
+        };
+
+        Block { header, transactions, evidence, unjails }
     };
 
-    let signable = serialize_header_for_signing(&header)?;
-    header.signature = sign_data(signing_key, &signable);
+    let outcome = preview_block(consensus_engine, &block);
+    block.header.state_root = outcome.state_root;
+    block.header.ledger_root = outcome.ledger_root;
+    block.header.total_fees = outcome.total_fees;
+
+    let signable = serialize_header_for_signing(&block.header)?;
+    block.header.signature = sign_data(signing_key, &signable);
+
+    append_block(consensus_engine, block.clone())?;
+    Ok(block)
+}
 
-    let block = Block { header, transactions };
-    chain_lock.push(block.clone());
+/// Checks what a block proves about itself, independent of our chain and
+/// clock: format and limits, the producer's signature, the merkle root and
+/// evidence and unjail commitments, and that every transaction is well formed
+/// and appears once. A block failing here was built or altered dishonestly,
+/// so the network penalizes the peer that sent it.
+pub fn verify_block_stateless(consensus_engine: &ConsensusEngine, block: &Block) -> Result<(), String> {
+    if block.header.subnet_id != consensus_engine.subnet_id {
+        return Err(format!(
+            "Block belongs to subnet {}, expected {}",
+            block.header.subnet_id, consensus_engine.subnet_id
+        ));
+    }
 
-    let neurons_lock = consensus_engine.neurons.lock();
-    let mut total_stake: u64 = 0;
-    let mut validator_stake: u64 = 0;
+    let pubkey_bytes = hex::decode(&block.header.validator)
+        .map_err(|e| format!("Invalid hex address: {}", e))?;
 
-    for neuron in neurons_lock.values() {
-        total_stake += neuron.staked_amount;
-        if let Some(ref val_addr) = neuron.validator {
-            if val_addr == &validator_address {
-                validator_stake += neuron.staked_amount;
-            }
-        }
+    let pubkey_array: [u8; 32] = pubkey_bytes
+        .try_into()
+        .map_err(|_| "Invalid length: Expected 32 bytes".to_string())?;
+
+    let pubkey = VerifyingKey::from_bytes(&pubkey_array)
+        .map_err(|e| format!("Failed to create VerifyingKey: {}", e))?;
+
+    if block.header.version != BLOCK_VERSION {
+        return Err(format!("Unsupported block version {}, expected {}", block.header.version, BLOCK_VERSION));
     }
 
-    drop(neurons_lock);
-    drop(chain_lock);
-    drop(mempool_lock);
+    if block.header.tx_count as usize != block.transactions.len() {
+        return Err(format!(
+            "Block declares {} transactions but carries {}",
+            block.header.tx_count,
+            block.transactions.len()
+        ));
+    }
+
+    if block.transactions.len() > consensus_engine.config.max_block_transactions {
+        return Err(format!(
+            "Block carries {} transactions, limit is {}",
+            block.transactions.len(),
+            consensus_engine.config.max_block_transactions
+        ));
+    }
+
+    if evidence_hash(&block.evidence) != block.header.evidence_hash {
+        return Err("Evidence hash mismatch".into());
+    }
+
+    if unjail_hash(&block.unjails) != block.header.unjail_hash {
+        return Err("Unjail hash mismatch".into());
+    }
 
+    let size = transactions_size(&block.transactions);
+    if size > consensus_engine.config.max_block_bytes {
+        return Err(format!(
+            "Block transactions take {} bytes, limit is {}",
+            size, consensus_engine.config.max_block_bytes
+        ));
+    }
+
+    let signable = serialize_header_for_signing(&block.header)?;
+    if !verify_data(&pubkey, &signable, &block.header.signature) {
+        return Err("Invalid block signature".into());
+    }
 
-    if total_stake > 0 {
-        let prob = consensus_probability(validator_stake, total_stake);
-        const BLOCK_REWARD: u64 = 10;
-        const REWARD_MULTIPLIER: f64 = 1.0;
-        let reward_float = REWARD_MULTIPLIER * (validator_stake as f64 / total_stake as f64) * (BLOCK_REWARD as f64);
-        let reward: u64 = reward_float.round() as u64;
+    let computed_merkle_root = compute_merkle_root(&block.transactions);
+    if block.header.merkle_root != computed_merkle_root {
+        return Err("Merkle root mismatch".into());
+    }
 
-        let mut ledger_lock = consensus_engine.ledger.lock();
-        if let Some(account) = ledger_lock.get_mut(&validator_address) {
-            account.balance += reward;
-            println!("Validator {} rewarded with {} tokens", validator_address, reward);
+    let mut hashes: HashSet<&str> = HashSet::new();
+    for tx in &block.transactions {
+        verify_transaction(tx).map_err(|e| format!("Invalid transaction {}: {}", tx.hash, e))?;
+        if !hashes.insert(&tx.hash) {
+            return Err(format!("Block carries transaction {} twice", tx.hash));
         }
     }
 
-    consensus_engine.persist_state();
-    Ok(block)
+    Ok(())
 }
 
+/// Full validation of a block against our chain: the stateless checks, then
+/// its place in the tree, slot, leader, evidence and unjails and, when it
+/// extends the tip, the post-state it commits to. Failures past the stateless
+/// checks can come from an honest peer that is ahead of us, on another
+/// branch or has a skewed clock.
 pub fn validate_block(
     consensus_engine: &mut ConsensusEngine,
     block: &Block,
 ) -> Result<(), String> {
     {
-        let pubkey_bytes = hex::decode(&block.header.validator)
-            .map_err(|e| format!("Invalid hex address: {}", e))?;
+        verify_block_stateless(consensus_engine, block)?;
 
-        let pubkey_array: [u8; 32] = pubkey_bytes
-            .try_into()
-            .map_err(|_| "Invalid length: Expected 32 bytes".to_string())?;
+        validate_block_evidence(consensus_engine, &block.evidence)?;
+        validate_block_unjails(consensus_engine, block)?;
 
-        let pubkey = VerifyingKey::from_bytes(&pubkey_array)
-            .map_err(|e| format!("Failed to create VerifyingKey: {}", e))?;
+        let parent = {
+            let tree_lock = consensus_engine.tree.lock();
+            if block.header.parent_hash == [0u8; 32] {
+                None
+            } else {
+                let parent = tree_lock.get(&block.header.parent_hash).ok_or("Unknown parent block")?;
+                Some(parent.block.header.clone())
+            }
+        };
+
+        let expected_height = parent.as_ref().map(|header| header.height + 1).unwrap_or(0);
+        if block.header.height != expected_height {
+            return Err(format!("Block height {} does not follow parent, expected {}", block.header.height, expected_height));
+        }
 
-        let validators_lock = consensus_engine.validators.lock();
+        let parent_slot = parent.as_ref().map(|header| header.slot);
+        validate_block_slot(consensus_engine, block.header.slot, parent_slot)?;
 
-        if !validators_lock.iter().any(|v| v.address == block.header.validator && v.active) {
-            return Err("Block validator is not active".into());
+        // Timestamps are whole seconds, so allow one second of truncation.
+        if block.header.timestamp * 1000 + 1000 <= slot_start_ms(consensus_engine, block.header.slot) {
+            return Err(format!("Block timestamp precedes the start of slot {}", block.header.slot));
         }
 
-        let signable = serialize_header_for_signing(&block.header)?;
-        if !verify_data(&pubkey, &signable, &block.header.signature) {
-            return Err("Invalid block signature".into());
+        // Jail state is only kept for the canonical chain, so the validator set
+        // and leader of a block on another branch are checked when fork choice
+        // applies it.
+        if parent_validators(consensus_engine, &block.header.parent_hash).is_some() {
+            validator_set_at(consensus_engine, &block.header.parent_hash, block.header.height)?;
+            match select_leader(consensus_engine, &block.header.parent_hash, block.header.height, block.header.slot) {
+                Some(leader) if leader == block.header.validator => {}
+                Some(leader) => {
+                    return Err(format!(
+                        "Block producer {} is not the leader for slot {}, expected {}",
+                        block.header.validator, block.header.slot, leader
+                    ));
+                }
+                None => return Err("No eligible leader for block slot".into()),
+            }
         }
 
-        let computed_merkle_root = compute_merkle_root(&block.transactions);
-        if block.header.merkle_root != computed_merkle_root {
-            return Err("Merkle root mismatch".into());
+        // The post-state can only be recomputed here when the block extends our
+        // tip; blocks on other branches are checked when fork choice applies them.
+        let extends_tip = {
+            let chain_lock = consensus_engine.chain.lock();
+            chain_lock.last().map(hash_block).unwrap_or([0u8; 32]) == block.header.parent_hash
+        };
+        if extends_tip {
+            if let Some(tx) = block.transactions.iter().find(|tx| get_transaction_result(consensus_engine, &tx.hash).is_some()) {
+                return Err(format!("Transaction {} was already executed", tx.hash));
+            }
+
+            let outcome = preview_block(consensus_engine, block);
+            let claimed = BlockOutcome::of_header(&block.header);
+            if outcome.state_root != claimed.state_root {
+                return Err("State root mismatch".into());
+            }
+            if outcome.ledger_root != claimed.ledger_root {
+                return Err("Ledger root mismatch".into());
+            }
+            if outcome.total_fees != claimed.total_fees {
+                return Err(format!("Block claims {} in fees but collects {}", claimed.total_fees, outcome.total_fees));
+            }
         }
 
         let now = Utc::now().timestamp() as u64;
@@ -115,6 +249,32 @@ pub fn validate_block(
     consensus_engine.persist_state();
     Ok(())
 }
+
+/// Validates a block and adds it to the block tree under the engine's import
+/// lock, so the state it was validated against is the one it is applied to.
+pub fn import_block(
+    consensus_engine: &mut ConsensusEngine,
+    block: Block,
+) -> Result<(), String> {
+    let import_lock = Arc::clone(&consensus_engine.import_lock);
+    let _guard = import_lock.lock();
+
+    validate_block(consensus_engine, &block)?;
+    append_block(consensus_engine, block)
+}
+
+/// Adds an already validated block to the block tree. Fork choice decides
+/// whether it extends or replaces the canonical chain. The caller holds the
+/// import lock.
+fn append_block(
+    consensus_engine: &mut ConsensusEngine,
+    block: Block,
+) -> Result<(), String> {
+    insert_block(consensus_engine, block).map(|_| ())
+}
+
+/// Root of the transaction tree; see `merkle::merkle_levels` for the layout.
+/// A block without transactions has an all-zero root.
 pub fn compute_merkle_root(
     transactions: &[Transaction]
 ) -> [u8; 32] {
@@ -122,36 +282,19 @@ pub fn compute_merkle_root(
         return [0; 32];
     }
 
-    let mut hashes: Vec<[u8; 32]> = transactions
-        .iter()
-        .map(|tx| {
-            let mut tx_clone = tx.clone();
-            tx_clone.hash.clear();
-            let bytes = bincode::serialize(&tx_clone).unwrap_or_default();
-            crypto_hash(&bytes)
-        })
-        .collect();
-
-    while hashes.len() > 1 {
-        let mut new_hashes = Vec::new();
-        for chunk in hashes.chunks(2) {
-            let mut hasher = Sha256::new();
-            hasher.update(&chunk[0]);
-            if chunk.len() == 2 {
-                hasher.update(&chunk[1]);
-            }
-            new_hashes.push(hasher.finalize().into());
-        }
-        hashes = new_hashes;
-    }
-
-    hashes[0]
+    merkle_levels(transactions).pop().map(|root| root[0]).unwrap_or([0; 32])
 }
 
 pub fn hash_block(
     block: &Block
 ) -> [u8; 32] {
-    let signable = serialize_header_for_signing(&block.header).unwrap_or_default();
+    hash_header(&block.header)
+}
+
+pub fn hash_header(
+    header: &BlockHeader
+) -> [u8; 32] {
+    let signable = serialize_header_for_signing(header).unwrap_or_default();
     crypto_hash(&signable)
 }
 
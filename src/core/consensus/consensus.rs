@@ -1,29 +1,33 @@
-use crate::core::consensus::block::produce_block;
+use crate::core::consensus::block::{hash_block, produce_block};
+use crate::core::consensus::finality::{add_vote, can_vote_for, has_voted, record_last_vote, sign_block_vote};
 use crate::core::consensus::model::ConsensusEngine;
+use crate::core::consensus::slot::{is_slot_open, time_until_next_slot};
+use crate::core::consensus::epoch::validator_set_of_block;
 use crate::core::consensus::validator::select_next_validator;
-use crate::core::staking::StakingModule;
-use crate::core::staking::distribute_rewards;
-use crate::core::nervous::NervousSystem;
+use crate::core::network::{Network, NetworkMessage};
 
 use ed25519_dalek::{SigningKey, VerifyingKey};
-use tokio::time::{sleep, Duration, Instant};
+use tokio::time::{sleep, Duration};
 use hex;
 
 pub async fn run_consensus_loop(
-    nervous_system: &mut NervousSystem,
     consensus_engine: &mut ConsensusEngine,
-    staking_module: &mut StakingModule,
     signing_key: &SigningKey,
+    network: &Network,
 ) {
-    let target_cycle = Duration::from_millis(500);
-
-    const REWARD_POOL: u64 = 50;
-    const ANNUAL_YIELD_PERCENT: f64 = 5.0;
+    let slot_duration = Duration::from_millis(consensus_engine.config.slot_duration_ms);
 
     loop {
-        let cycle_start = Instant::now();
+        if !network.is_synced() {
+            sleep(slot_duration).await;
+            continue;
+        }
 
-        if let Some(next_validator) = select_next_validator(consensus_engine) {
+        // Only the leader of the current slot produces. If it is offline the
+        // slot stays empty and the next slot's leader builds on the same tip.
+        if is_slot_open(consensus_engine)
+            && let Some(next_validator) = select_next_validator(consensus_engine)
+        {
             let verifying_key = VerifyingKey::from(signing_key);
             let my_address = hex::encode(verifying_key.to_bytes());
 
@@ -31,12 +35,13 @@ pub async fn run_consensus_loop(
                 match produce_block(consensus_engine, signing_key) {
                     Ok(block) => {
                         println!(
-                            "⚡ Block produced: {} transactions, timestamp: {}",
+                            "⚡ Block produced: {} transactions, {} in fees, timestamp: {}",
                             block.transactions.len(),
+                            block.header.total_fees,
                             block.header.timestamp
                         );
 
-                        distribute_rewards(nervous_system, &mut staking_module.clone(), REWARD_POOL, ANNUAL_YIELD_PERCENT);
+                        network.broadcast(NetworkMessage::Block(Box::new(block))).await;
                     }
                     Err(err) => {
                         eprintln!("Block production error: {}", err);
@@ -45,13 +50,58 @@ pub async fn run_consensus_loop(
             }
         }
 
-        let elapsed = cycle_start.elapsed();
-        let sleep_duration = if elapsed < target_cycle {
-            target_cycle - elapsed
-        } else {
-            Duration::from_secs(1)
-        };
+        vote_for_tip(consensus_engine, signing_key, network).await;
+
+        sleep(Duration::from_millis(time_until_next_slot(consensus_engine))).await;
+    }
+}
+
+/// Signs the current chain tip if this node is an active validator that has not
+/// voted for it yet and the tip extends its last vote, and gossips the vote to
+/// the subnet.
+async fn vote_for_tip(
+    consensus_engine: &mut ConsensusEngine,
+    signing_key: &SigningKey,
+    network: &Network,
+) {
+    let tip = {
+        let chain_lock = consensus_engine.chain.lock();
+        chain_lock.last().map(|blk| (hash_block(blk), blk.header.height))
+    };
+    let Some((block_hash, height)) = tip else {
+        return;
+    };
 
-        sleep(sleep_duration).await;
+    let my_address = hex::encode(VerifyingKey::from(signing_key).to_bytes());
+    match validator_set_of_block(consensus_engine, &block_hash) {
+        Ok(set) if set.contains(&my_address) => {}
+        Ok(_) => return,
+        Err(err) => {
+            eprintln!("Block vote error: {}", err);
+            return;
+        }
+    }
+    if has_voted(consensus_engine, &block_hash, &my_address) || !can_vote_for(consensus_engine, &block_hash) {
+        return;
+    }
+
+    let vote = match sign_block_vote(consensus_engine, block_hash, height, signing_key) {
+        Ok(vote) => vote,
+        Err(err) => {
+            eprintln!("Block vote error: {}", err);
+            return;
+        }
+    };
+    record_last_vote(consensus_engine, &vote);
+
+    match add_vote(consensus_engine, vote.clone()) {
+        Ok(certificate) => {
+            network.broadcast(NetworkMessage::BlockVote(vote)).await;
+            if let Some(certificate) = certificate {
+                println!("✅ Block at height {} finalized", certificate.height);
+                network.broadcast(NetworkMessage::FinalityCertificate(certificate)).await;
+            }
+        }
+        Err(err) => eprintln!("Block vote error: {}", err),
     }
 }
@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use crate::core::consensus::block::hash_block;
+use crate::core::consensus::model::ConsensusEngine;
+use crate::core::types::Address;
+
+/// Validators allowed to produce and vote on blocks during one epoch, with the
+/// stake backing each of them. Sorted by address.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EpochValidatorSet {
+    pub epoch: u64,
+    pub start_height: u64,
+    pub validators: Vec<(Address, u64)>,
+}
+
+impl EpochValidatorSet {
+    pub fn total_stake(&self) -> u64 {
+        self.validators.iter().map(|(_, stake)| stake).sum()
+    }
+
+    pub fn contains(&self, address: &str) -> bool {
+        self.validators.iter().any(|(validator, _)| validator == address)
+    }
+}
+
+pub fn epoch_of(consensus_engine: &ConsensusEngine, height: u64) -> u64 {
+    height / consensus_engine.config.epoch_length.max(1)
+}
+
+/// Whether the block at `height` is the last one of its epoch, so applying it
+/// fixes the validator set of the next epoch.
+pub fn is_epoch_boundary(consensus_engine: &ConsensusEngine, height: u64) -> bool {
+    (height + 1).is_multiple_of(consensus_engine.config.epoch_length.max(1))
+}
+
+/// Hash of the block that closed the epoch before the one containing
+/// `height`, found by walking back from `parent_hash`. It anchors the epoch's
+/// validator set to one branch. Epoch 0 is anchored at the all-zero hash.
+pub fn epoch_anchor(consensus_engine: &ConsensusEngine, parent_hash: &[u8; 32], height: u64) -> Option<[u8; 32]> {
+    let epoch = epoch_of(consensus_engine, height);
+    if epoch == 0 {
+        return Some([0u8; 32]);
+    }
+    let boundary = epoch * consensus_engine.config.epoch_length.max(1) - 1;
+
+    let tree_lock = consensus_engine.tree.lock();
+    let mut cursor = *parent_hash;
+    loop {
+        let node = tree_lock.get(&cursor)?;
+        if node.height == boundary {
+            return Some(cursor);
+        }
+        if node.height < boundary {
+            return None;
+        }
+        cursor = node.block.header.parent_hash;
+    }
+}
+
+/// Validator set in force for a block at `height` on top of `parent_hash`.
+/// Fails for an epoch whose set has not been snapshotted on that branch,
+/// either at genesis or by the previous epoch's last block.
+pub fn validator_set_at(consensus_engine: &ConsensusEngine, parent_hash: &[u8; 32], height: u64) -> Result<EpochValidatorSet, String> {
+    let epoch = epoch_of(consensus_engine, height);
+    epoch_anchor(consensus_engine, parent_hash, height)
+        .and_then(|anchor| consensus_engine.epochs.lock().get(&anchor).cloned())
+        .ok_or_else(|| format!("No validator set snapshot for epoch {}", epoch))
+}
+
+/// Validator set in force for the block `block_hash` in the block tree.
+pub fn validator_set_of_block(consensus_engine: &ConsensusEngine, block_hash: &[u8; 32]) -> Result<EpochValidatorSet, String> {
+    let (parent_hash, height) = {
+        let tree_lock = consensus_engine.tree.lock();
+        let node = tree_lock.get(block_hash).ok_or("Unknown block")?;
+        (node.block.header.parent_hash, node.height)
+    };
+    validator_set_at(consensus_engine, &parent_hash, height)
+}
+
+/// Snapshots the validator set of epoch 0 from the genesis state, before any
+/// block is produced or imported. Does nothing once the snapshot exists.
+pub fn init_genesis_validator_set(consensus_engine: &ConsensusEngine) {
+    if !consensus_engine.epochs.lock().contains_key(&[0u8; 32]) {
+        let set = compute_validator_set(consensus_engine, 0);
+        store_validator_set(consensus_engine, [0u8; 32], set);
+    }
+}
+
+/// Validator set of `epoch` on the canonical chain.
+pub fn get_validator_set(consensus_engine: &ConsensusEngine, epoch: u64) -> Option<EpochValidatorSet> {
+    let anchor = match epoch.checked_mul(consensus_engine.config.epoch_length.max(1)) {
+        Some(0) => [0u8; 32],
+        Some(start) => hash_block(consensus_engine.chain.lock().get(start as usize - 1)?),
+        None => return None,
+    };
+    consensus_engine.epochs.lock().get(&anchor).cloned()
+}
+
+/// Picks the validator set for `epoch` from the current state: active
+/// validators whose own neuron holds at least `min_self_stake`, the
+/// `max_validators` with the most backing stake, ties broken by address.
+pub fn compute_validator_set(consensus_engine: &ConsensusEngine, epoch: u64) -> EpochValidatorSet {
+    let config = &consensus_engine.config;
+    let mut validators: Vec<(Address, u64)> = {
+        let neurons_lock = consensus_engine.neurons.lock();
+        let validators_lock = consensus_engine.validators.lock();
+
+        validators_lock
+            .iter()
+            .filter(|validator| validator.active)
+            .filter(|validator| {
+                neurons_lock
+                    .get(&validator.neuron_id)
+                    .is_some_and(|neuron| neuron.staked_amount >= config.min_self_stake)
+            })
+            .map(|validator| {
+                let stake = neurons_lock
+                    .values()
+                    .filter(|neuron| neuron.validator.as_ref() == Some(&validator.address))
+                    .map(|neuron| neuron.staked_amount)
+                    .sum();
+                (validator.address.clone(), stake)
+            })
+            .filter(|(_, stake)| *stake > 0)
+            .collect()
+    };
+
+    validators.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    validators.dedup_by(|a, b| a.0 == b.0);
+    validators.truncate(config.max_validators);
+    validators.sort_by(|a, b| a.0.cmp(&b.0));
+
+    EpochValidatorSet {
+        epoch,
+        start_height: epoch * config.epoch_length.max(1),
+        validators,
+    }
+}
+
+/// Stores the set snapshotted by the block `anchor`. Snapshots of every branch
+/// are kept until finality prunes the branch.
+pub fn store_validator_set(consensus_engine: &ConsensusEngine, anchor: [u8; 32], set: EpochValidatorSet) {
+    let serialized = bincode::serialize(&set).unwrap();
+    let key = format!("validator_set_{}", hex::encode(anchor));
+    consensus_engine.db.put(key.as_bytes(), serialized).unwrap();
+    consensus_engine.epochs.lock().insert(anchor, set);
+}
+
+/// Drops the snapshots anchored at blocks that are no longer in the block tree.
+pub fn prune_validator_sets(consensus_engine: &ConsensusEngine) {
+    let tree_lock = consensus_engine.tree.lock();
+    consensus_engine.epochs.lock().retain(|anchor, _| {
+        let keep = *anchor == [0u8; 32] || tree_lock.contains(anchor);
+        if !keep {
+            let key = format!("validator_set_{}", hex::encode(anchor));
+            consensus_engine.db.delete(key.as_bytes()).unwrap();
+        }
+        keep
+    });
+}
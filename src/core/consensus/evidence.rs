@@ -0,0 +1,238 @@
+use ed25519_dalek::VerifyingKey;
+use hex;
+use crate::core::consensus::block::{hash_header, serialize_header_for_signing};
+use crate::core::consensus::crypto_hash;
+use crate::core::consensus::model::ConsensusEngine;
+use crate::core::crypto::verify_data;
+use crate::core::types::{BlockHeader, Evidence};
+
+/// Most pieces of evidence a single block may carry.
+pub const MAX_EVIDENCE_PER_BLOCK: usize = 16;
+
+/// Share of a double-signing validator's backing stake that is slashed.
+pub const SLASH_PERCENT: u64 = 10;
+
+/// Identifies a double-sign independently of the order its headers come in.
+pub fn evidence_id(evidence: &Evidence) -> [u8; 32] {
+    let mut hashes = [hash_header(&evidence.first), hash_header(&evidence.second)];
+    hashes.sort();
+    crypto_hash(&hashes.concat())
+}
+
+/// Commitment to a block's evidence list stored in `BlockHeader::evidence_hash`.
+pub fn evidence_hash(evidence: &[Evidence]) -> [u8; 32] {
+    if evidence.is_empty() {
+        return [0u8; 32];
+    }
+    crypto_hash(&bincode::serialize(evidence).unwrap_or_default())
+}
+
+/// Checks that both headers are distinct, are for the same height and subnet,
+/// and carry valid signatures from the same known validator.
+pub fn verify_evidence(consensus_engine: &ConsensusEngine, evidence: &Evidence) -> Result<(), String> {
+    let (first, second) = (&evidence.first, &evidence.second);
+
+    if first.validator != second.validator {
+        return Err("Evidence headers are signed by different validators".into());
+    }
+    if first.height != second.height {
+        return Err("Evidence headers are for different heights".into());
+    }
+    if first.subnet_id != consensus_engine.subnet_id || second.subnet_id != consensus_engine.subnet_id {
+        return Err("Evidence headers belong to another subnet".into());
+    }
+    if hash_header(first) == hash_header(second) {
+        return Err("Evidence headers are identical".into());
+    }
+
+    if !consensus_engine.validators.lock().iter().any(|validator| validator.address == first.validator) {
+        return Err(format!("Evidence names unknown validator {}", first.validator));
+    }
+
+    let pubkey_bytes: [u8; 32] = hex::decode(&first.validator)
+        .map_err(|e| format!("Invalid validator address: {}", e))?
+        .try_into()
+        .map_err(|_| "Invalid validator address length".to_string())?;
+    let pubkey = VerifyingKey::from_bytes(&pubkey_bytes)
+        .map_err(|e| format!("Invalid validator public key: {}", e))?;
+
+    for header in [first, second] {
+        let signable = serialize_header_for_signing(header)?;
+        if !verify_data(&pubkey, &signable, &header.signature) {
+            return Err("Evidence header signature does not verify".into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a block on the canonical chain has already executed this evidence.
+pub fn is_evidence_executed(consensus_engine: &ConsensusEngine, evidence: &Evidence) -> bool {
+    let key = format!("evidence_{}", hex::encode(evidence_id(evidence)));
+    matches!(consensus_engine.db.get(key.as_bytes()), Ok(Some(_)))
+}
+
+/// Verifies `evidence` and adds it to the pending pool. Returns `false` if it
+/// is already pending or already executed.
+pub fn add_evidence(consensus_engine: &mut ConsensusEngine, evidence: Evidence) -> Result<bool, String> {
+    verify_evidence(consensus_engine, &evidence)?;
+
+    if is_evidence_executed(consensus_engine, &evidence) {
+        return Ok(false);
+    }
+
+    let id = evidence_id(&evidence);
+    let mut pending_lock = consensus_engine.evidence.lock();
+    if pending_lock.iter().any(|pending| evidence_id(pending) == id) {
+        return Ok(false);
+    }
+
+    println!("⚠️ Double-signing detected for validator {} at height {}", evidence.first.validator, evidence.first.height);
+    pending_lock.push(evidence);
+    Ok(true)
+}
+
+/// Looks for a known header signed by the same validator at the same height
+/// as `header`. If one exists, the pair becomes new pending evidence.
+pub fn detect_equivocation(consensus_engine: &mut ConsensusEngine, header: &BlockHeader) -> Option<Evidence> {
+    let header_hash = hash_header(header);
+    let conflicting = {
+        let tree_lock = consensus_engine.tree.lock();
+        tree_lock
+            .nodes
+            .iter()
+            .find(|(hash, node)| {
+                **hash != header_hash
+                    && node.height == header.height
+                    && node.block.header.validator == header.validator
+            })
+            .map(|(_, node)| node.block.header.clone())
+    }?;
+
+    let evidence = Evidence { first: conflicting, second: header.clone() };
+    match add_evidence(consensus_engine, evidence.clone()) {
+        Ok(true) => Some(evidence),
+        _ => None,
+    }
+}
+
+/// Pending evidence a new block should carry.
+pub fn pending_evidence(consensus_engine: &ConsensusEngine) -> Vec<Evidence> {
+    let pending_lock = consensus_engine.evidence.lock();
+    pending_lock
+        .iter()
+        .filter(|evidence| !is_evidence_executed(consensus_engine, evidence))
+        .take(MAX_EVIDENCE_PER_BLOCK)
+        .cloned()
+        .collect()
+}
+
+/// Checks the evidence a block carries before it is executed.
+pub fn validate_block_evidence(consensus_engine: &ConsensusEngine, evidence: &[Evidence]) -> Result<(), String> {
+    if evidence.len() > MAX_EVIDENCE_PER_BLOCK {
+        return Err(format!("Block carries {} pieces of evidence, limit is {}", evidence.len(), MAX_EVIDENCE_PER_BLOCK));
+    }
+
+    let mut seen = Vec::new();
+    for item in evidence {
+        verify_evidence(consensus_engine, item)?;
+
+        let id = evidence_id(item);
+        if seen.contains(&id) || is_evidence_executed(consensus_engine, item) {
+            return Err("Block carries evidence that was already executed".into());
+        }
+        seen.push(id);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+    use crate::core::consensus::block::import_block;
+    use crate::core::crypto::sign_data;
+    use crate::core::test_utils::{address_of, build_block, signing_key, test_engine};
+
+    /// Engine on top of a genesis block, plus two differently timestamped
+    /// headers for height 1 signed by the same leader; the first is imported.
+    fn double_signed(name: &str) -> (ConsensusEngine, Vec<SigningKey>, BlockHeader, BlockHeader) {
+        let keys: Vec<SigningKey> = (1..=3).map(signing_key).collect();
+        let mut engine = test_engine(name, &keys, 1_000, 0);
+        let genesis = build_block(&engine, &keys, 1);
+        import_block(&mut engine, genesis).unwrap();
+
+        let block = build_block(&engine, &keys, 2);
+        let key = keys.iter().find(|key| address_of(key) == block.header.validator).unwrap();
+        let mut second = block.header.clone();
+        second.timestamp += 1;
+        second.signature = sign_data(key, &serialize_header_for_signing(&second).unwrap());
+
+        let first = block.header.clone();
+        import_block(&mut engine, block).unwrap();
+        (engine, keys, first, second)
+    }
+
+    #[test]
+    fn verify_rejects_malformed_evidence() {
+        let (engine, keys, first, second) = double_signed("evidence_verify");
+        assert!(verify_evidence(&engine, &Evidence { first: first.clone(), second: second.clone() }).is_ok());
+
+        let identical = Evidence { first: first.clone(), second: first.clone() };
+        assert!(verify_evidence(&engine, &identical).is_err());
+
+        let mut unsigned = second.clone();
+        unsigned.timestamp += 1;
+        assert!(verify_evidence(&engine, &Evidence { first: first.clone(), second: unsigned }).is_err());
+
+        let mut other_height = second.clone();
+        other_height.height += 1;
+        let key = keys.iter().find(|key| address_of(key) == first.validator).unwrap();
+        other_height.signature = sign_data(key, &serialize_header_for_signing(&other_height).unwrap());
+        assert!(verify_evidence(&engine, &Evidence { first, second: other_height }).is_err());
+    }
+
+    #[test]
+    fn executed_evidence_slashes_and_deactivates() {
+        let (mut engine, keys, _first, second) = double_signed("evidence_slash");
+        let offender = second.validator.clone();
+
+        let evidence = detect_equivocation(&mut engine, &second).unwrap();
+        assert_eq!(pending_evidence(&engine).len(), 1);
+        assert!(!add_evidence(&mut engine, evidence.clone()).unwrap());
+
+        let stake_of = |engine: &ConsensusEngine, address: &str| {
+            engine
+                .neurons
+                .lock()
+                .values()
+                .find(|neuron| neuron.validator.as_deref() == Some(address))
+                .map(|neuron| neuron.staked_amount)
+                .unwrap()
+        };
+        let honest = keys.iter().map(address_of).find(|address| *address != offender).unwrap();
+        let (offender_before, honest_before) = (stake_of(&engine, &offender), stake_of(&engine, &honest));
+
+        let block = build_block(&engine, &keys, 3);
+        assert_eq!(block.evidence.len(), 1);
+        import_block(&mut engine, block).unwrap();
+
+        // The block also pays staking rewards, which the honest validator
+        // shows; the offender earns at most as much on its slashed stake.
+        let slashed = offender_before - offender_before * SLASH_PERCENT / 100;
+        let reward = stake_of(&engine, &honest) - honest_before;
+        let offender_after = stake_of(&engine, &offender);
+        assert!(offender_after >= slashed && offender_after <= slashed + reward);
+
+        let validators = engine.validators.lock().clone();
+        let validator = validators.iter().find(|validator| validator.address == offender).unwrap();
+        assert!(!validator.active);
+        assert_eq!(validator.jailed_at, None);
+
+        assert!(is_evidence_executed(&engine, &evidence));
+        assert!(pending_evidence(&engine).is_empty());
+        assert!(!add_evidence(&mut engine, evidence.clone()).unwrap());
+        assert!(validate_block_evidence(&engine, &[evidence]).is_err());
+    }
+}
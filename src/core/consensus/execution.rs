@@ -0,0 +1,504 @@
+use std::collections::HashMap;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use crate::core::consensus::ValidatorInfo;
+use crate::core::consensus::epoch::{compute_validator_set, epoch_of, is_epoch_boundary, store_validator_set};
+use crate::core::consensus::evidence::{SLASH_PERCENT, evidence_id};
+use crate::core::consensus::liveness::{apply_unjails, missed_slots, record_liveness};
+use crate::core::consensus::math::staking_yield;
+use crate::core::consensus::model::{Account, ConsensusConfig, ConsensusEngine};
+use crate::core::consensus::block::hash_block;
+use crate::core::consensus::state::{compute_ledger_root, state_root_of};
+use crate::core::types::{Address, Block, BlockHeader, Neuron, Transaction, TransactionStatus};
+
+const BLOCK_REWARD: u64 = 10;
+const REWARD_MULTIPLIER: f64 = 1.0;
+const REWARD_POOL: u64 = 50;
+const ANNUAL_YIELD_PERCENT: f64 = 5.0;
+
+/// State touched by a block, captured before the block was applied so the
+/// block can be rolled back during a reorg.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BlockUndo {
+    pub ledger: Vec<(String, Option<Account>)>,
+    pub neurons: Vec<(u64, Option<Neuron>)>,
+    /// Validators before the block updated their liveness counters, jailed,
+    /// unjailed or deactivated anyone.
+    pub validators: Option<Vec<ValidatorInfo>>,
+}
+
+/// Outcome of executing one transaction inside a canonical block.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TransactionResult {
+    pub tx_hash: String,
+    pub block_height: u64,
+    pub status: TransactionStatus,
+    pub fee: u64,
+    pub error: Option<String>,
+}
+
+/// What executing a block yields, as committed to in its header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockOutcome {
+    pub state_root: [u8; 32],
+    pub ledger_root: [u8; 32],
+    pub total_fees: u64,
+}
+
+impl BlockOutcome {
+    pub fn of_header(header: &BlockHeader) -> Self {
+        Self {
+            state_root: header.state_root,
+            ledger_root: header.ledger_root,
+            total_fees: header.total_fees,
+        }
+    }
+}
+
+/// Applies a block's effects on top of the current state: validators proven
+/// to have double-signed are slashed and deactivated, jailed validators named
+/// by its unjail requests are restored, validator liveness is recorded and
+/// validators missing too many slots are jailed, its transactions leave
+/// the mempool and are executed in order, the producer earns the block reward
+/// and staked neurons accrue rewards. The last block of an epoch snapshots the
+/// validator set of the next epoch.
+pub fn apply_block(consensus_engine: &mut ConsensusEngine, block: &Block) -> BlockUndo {
+    let mut undo = BlockUndo::default();
+    let results;
+    let missed = missed_slots(consensus_engine, block);
+
+    {
+        let mut ledger_lock = consensus_engine.ledger.lock();
+        let mut neurons_lock = consensus_engine.neurons.lock();
+
+        undo.ledger = touched_accounts(block)
+            .into_iter()
+            .map(|address| {
+                let account = ledger_lock.get(&address).cloned();
+                (address, account)
+            })
+            .collect();
+        undo.neurons = neurons_lock.iter().map(|(id, neuron)| (*id, Some(neuron.clone()))).collect();
+
+        results = execute_effects(&consensus_engine.config, &mut ledger_lock, &mut neurons_lock, block);
+        {
+            let mut tree_lock = consensus_engine.ledger_tree.lock();
+            for (address, _) in &undo.ledger {
+                tree_lock.update(address, ledger_lock.get(address));
+            }
+        }
+
+        {
+            let mut validators_lock = consensus_engine.validators.lock();
+            undo.validators = Some(validators_lock.clone());
+            for address in execute_validator_effects(&consensus_engine.config, &mut validators_lock, block, &missed) {
+                println!("⛓️ Validator {} jailed at height {} for missing slots", address, block.header.height);
+            }
+        }
+
+        // Drop the included transactions and anything else whose nonce the
+        // block has used up.
+        let mut mempool_lock = consensus_engine.mempool.lock();
+        mempool_lock.retain(|tx| {
+            let account_nonce = ledger_lock.get(&tx.from).map(|account| account.nonce).unwrap_or(0);
+            tx.nonce >= account_nonce && !block.transactions.iter().any(|included| included.hash == tx.hash)
+        });
+    }
+
+    // A result, once recorded, belongs to the block that executed the
+    // transaction and is never overwritten.
+    for result in &results {
+        let key = format!("tx_result_{}", result.tx_hash);
+        if matches!(consensus_engine.db.get(key.as_bytes()), Ok(Some(_))) {
+            continue;
+        }
+        let serialized = bincode::serialize(result).unwrap();
+        consensus_engine.db.put(key.as_bytes(), serialized).unwrap();
+    }
+
+    if !block.evidence.is_empty() {
+        let ids: Vec<[u8; 32]> = block.evidence.iter().map(evidence_id).collect();
+        for id in &ids {
+            let key = format!("evidence_{}", hex::encode(id));
+            consensus_engine.db.put(key.as_bytes(), block.header.height.to_be_bytes()).unwrap();
+        }
+        consensus_engine.evidence.lock().retain(|pending| !ids.contains(&evidence_id(pending)));
+    }
+
+    {
+        let validators_lock = consensus_engine.validators.lock();
+        consensus_engine.unjails.lock().retain(|pending| {
+            validators_lock
+                .iter()
+                .any(|v| v.address == pending.validator && v.jailed_at == Some(pending.jailed_at))
+        });
+    }
+
+    // The last block of an epoch fixes who validates the next one, including
+    // any slashing and deactivation it just executed.
+    if is_epoch_boundary(consensus_engine, block.header.height) {
+        let next_epoch = epoch_of(consensus_engine, block.header.height) + 1;
+        let set = compute_validator_set(consensus_engine, next_epoch);
+        store_validator_set(consensus_engine, hash_block(block), set);
+    }
+
+    consensus_engine.persist_neurons();
+    undo
+}
+
+/// Outcome the chain would have after applying `block` on top of the current
+/// state, computed on a copy without touching the engine.
+pub fn preview_block(consensus_engine: &ConsensusEngine, block: &Block) -> BlockOutcome {
+    let mut ledger = consensus_engine.ledger.lock().clone();
+    let mut neurons = consensus_engine.neurons.lock().clone();
+    let touched: Vec<(String, Option<Account>)> = touched_accounts(block)
+        .into_iter()
+        .map(|address| {
+            let account = ledger.get(&address).cloned();
+            (address, account)
+        })
+        .collect();
+    let neurons_before = neurons.clone();
+    let results = execute_effects(&consensus_engine.config, &mut ledger, &mut neurons, block);
+    let mut tree = consensus_engine.ledger_tree.lock().clone();
+    for (address, _) in &touched {
+        tree.update(address, ledger.get(address));
+    }
+
+    let missed = missed_slots(consensus_engine, block);
+    let validators_before = consensus_engine.validators.lock().clone();
+    let mut validators = validators_before.clone();
+    execute_validator_effects(&consensus_engine.config, &mut validators, block, &missed);
+
+    let parent_root = consensus_engine.chain.lock().last().map(|blk| blk.header.state_root).unwrap_or([0u8; 32]);
+
+    BlockOutcome {
+        state_root: state_root_of(
+            &parent_root,
+            &touched,
+            &ledger,
+            &neurons_before,
+            &neurons,
+            &validators_before,
+            &validators,
+        ),
+        ledger_root: tree.root(),
+        total_fees: results.iter().map(|result| result.fee).sum(),
+    }
+}
+
+/// Outcome of a block that is already applied as the canonical tip, read back
+/// from the live state, its undo record and the stored transaction results.
+pub fn applied_outcome(consensus_engine: &ConsensusEngine, block: &Block) -> BlockOutcome {
+    let parent_root = {
+        let chain_lock = consensus_engine.chain.lock();
+        chain_lock.iter().rev().nth(1).map(|blk| blk.header.state_root).unwrap_or([0u8; 32])
+    };
+    let undo = consensus_engine.undo.lock().get(&hash_block(block)).cloned().unwrap_or_default();
+    let neurons_before: HashMap<u64, Neuron> = undo
+        .neurons
+        .into_iter()
+        .filter_map(|(id, neuron)| neuron.map(|neuron| (id, neuron)))
+        .collect();
+    let validators = consensus_engine.validators.lock().clone();
+    let validators_before = undo.validators.unwrap_or_else(|| validators.clone());
+    let state_root = state_root_of(
+        &parent_root,
+        &undo.ledger,
+        &consensus_engine.ledger.lock(),
+        &neurons_before,
+        &consensus_engine.neurons.lock(),
+        &validators_before,
+        &validators,
+    );
+
+    BlockOutcome {
+        state_root,
+        ledger_root: compute_ledger_root(consensus_engine),
+        total_fees: block
+            .transactions
+            .iter()
+            .filter_map(|tx| get_transaction_result(consensus_engine, &tx.hash))
+            .map(|result| result.fee)
+            .sum(),
+    }
+}
+
+/// Accounts whose entries `execute_effects` may change for this block, in the
+/// order they are first touched.
+fn touched_accounts(block: &Block) -> Vec<String> {
+    let mut accounts: Vec<String> = Vec::new();
+    let addresses = block
+        .transactions
+        .iter()
+        .flat_map(|tx| [&tx.from, &tx.to])
+        .chain(std::iter::once(&block.header.validator));
+
+    for address in addresses {
+        if !accounts.contains(address) {
+            accounts.push(address.clone());
+        }
+    }
+    accounts
+}
+
+fn execute_effects(
+    config: &ConsensusConfig,
+    ledger: &mut HashMap<String, Account>,
+    neurons: &mut HashMap<u64, Neuron>,
+    block: &Block,
+) -> Vec<TransactionResult> {
+    for evidence in &block.evidence {
+        slash_stake(neurons, &evidence.first.validator);
+    }
+
+    let results = block
+        .transactions
+        .iter()
+        .map(|tx| {
+            let outcome = execute_transaction(config, ledger, tx);
+            TransactionResult {
+                tx_hash: tx.hash.clone(),
+                block_height: block.header.height,
+                status: if outcome.is_ok() { TransactionStatus::Completed } else { TransactionStatus::Failed },
+                fee: if outcome.is_ok() { tx.fee } else { 0 },
+                error: outcome.err(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let validator_address = &block.header.validator;
+
+    let total_fees: u64 = results.iter().map(|result| result.fee).sum();
+    let burned = total_fees * config.fee_burn_percent.min(100) / 100;
+    if let Some(account) = ledger.get_mut(validator_address) {
+        account.balance += total_fees - burned;
+    }
+
+    let total_stake: u64 = neurons.values().map(|neuron| neuron.staked_amount).sum();
+    let validator_stake: u64 = neurons
+        .values()
+        .filter(|neuron| neuron.validator.as_ref() == Some(validator_address))
+        .map(|neuron| neuron.staked_amount)
+        .sum();
+
+    if total_stake > 0 {
+        let reward_float = REWARD_MULTIPLIER * (validator_stake as f64 / total_stake as f64) * (BLOCK_REWARD as f64);
+        let reward: u64 = reward_float.round() as u64;
+
+        if let Some(account) = ledger.get_mut(validator_address) {
+            account.balance += reward;
+        }
+    }
+
+    apply_staking_rewards(neurons, REWARD_POOL, ANNUAL_YIELD_PERCENT);
+    results
+}
+
+/// Changes a block makes to the validators: double-signers named by its
+/// evidence are deactivated, jailed validators named by its unjail requests
+/// are restored and liveness is recorded. Returns the validators it jailed.
+fn execute_validator_effects(
+    config: &ConsensusConfig,
+    validators: &mut [ValidatorInfo],
+    block: &Block,
+    missed: &[(u64, Address)],
+) -> Vec<Address> {
+    for evidence in &block.evidence {
+        if let Some(validator) = validators.iter_mut().find(|v| v.address == evidence.first.validator) {
+            // A double-signer is deactivated for good and cannot unjail.
+            validator.active = false;
+            validator.jailed_at = None;
+        }
+    }
+
+    apply_unjails(validators, &block.unjails);
+    record_liveness(config, validators, block, missed)
+}
+
+/// Burns `SLASH_PERCENT` of the stake of every neuron backing `validator`.
+fn slash_stake(neurons: &mut HashMap<u64, Neuron>, validator: &str) {
+    for neuron in neurons.values_mut() {
+        if neuron.validator.as_deref() == Some(validator) {
+            neuron.staked_amount -= neuron.staked_amount * SLASH_PERCENT / 100;
+        }
+    }
+}
+
+/// Moves `tx.amount` from sender to receiver, takes `tx.fee` from the sender
+/// and uses up its nonce. Every check runs before anything changes, so a
+/// failed transaction leaves the ledger untouched: it pays no fee and its
+/// nonce stays free for a transaction that can pay.
+pub fn execute_transaction(config: &ConsensusConfig, ledger: &mut HashMap<String, Account>, tx: &Transaction) -> Result<(), String> {
+    let sender = ledger.get(&tx.from).ok_or("Sender account does not exist")?;
+    if tx.nonce != sender.nonce {
+        return Err(format!("Invalid nonce {}: expected {}", tx.nonce, sender.nonce));
+    }
+    if tx.fee < config.min_fee {
+        return Err(format!("Transaction fee {} is below the minimum fee {}", tx.fee, config.min_fee));
+    }
+
+    let cost = tx.amount.saturating_add(tx.fee);
+    if sender.balance < cost {
+        return Err(format!(
+            "Insufficient funds: Sender balance is {} but transaction costs {}",
+            sender.balance, cost
+        ));
+    }
+    if !ledger.contains_key(&tx.to) {
+        return Err("Receiver account does not exist".to_string());
+    }
+
+    if let Some(sender) = ledger.get_mut(&tx.from) {
+        sender.nonce += 1;
+        sender.balance -= cost;
+    }
+    if let Some(receiver) = ledger.get_mut(&tx.to) {
+        receiver.balance += tx.amount;
+    }
+    Ok(())
+}
+
+pub fn get_transaction_result(consensus_engine: &ConsensusEngine, tx_hash: &str) -> Option<TransactionResult> {
+    let key = format!("tx_result_{}", tx_hash);
+    let value = consensus_engine.db.get(key.as_bytes()).ok()??;
+    bincode::deserialize(&value).ok()
+}
+
+/// Restores the state captured in `undo` and returns the block's transactions
+/// to the mempool and its evidence and unjail requests to the pending pools.
+pub fn revert_block(consensus_engine: &mut ConsensusEngine, block: &Block, undo: BlockUndo) {
+    {
+        let mut ledger_lock = consensus_engine.ledger.lock();
+        let mut tree_lock = consensus_engine.ledger_tree.lock();
+        for (address, account) in undo.ledger.into_iter().rev() {
+            tree_lock.update(&address, account.as_ref());
+            match account {
+                Some(account) => {
+                    ledger_lock.insert(address, account);
+                }
+                None => {
+                    ledger_lock.remove(&address);
+                }
+            }
+        }
+    }
+
+    if let Some(validators) = undo.validators {
+        *consensus_engine.validators.lock() = validators;
+    }
+
+    {
+        let mut pending_lock = consensus_engine.unjails.lock();
+        for unjail in &block.unjails {
+            if !pending_lock.contains(unjail) {
+                pending_lock.push(unjail.clone());
+            }
+        }
+    }
+
+    if !block.evidence.is_empty() {
+        let mut pending_lock = consensus_engine.evidence.lock();
+        for evidence in &block.evidence {
+            let id = evidence_id(evidence);
+            let key = format!("evidence_{}", hex::encode(id));
+            consensus_engine.db.delete(key.as_bytes()).unwrap();
+            if !pending_lock.iter().any(|pending| evidence_id(pending) == id) {
+                pending_lock.push(evidence.clone());
+            }
+        }
+    }
+
+    {
+        let mut neurons_lock = consensus_engine.neurons.lock();
+        for (id, neuron) in undo.neurons.into_iter().rev() {
+            match neuron {
+                Some(neuron) => {
+                    neurons_lock.insert(id, neuron);
+                }
+                None => {
+                    neurons_lock.remove(&id);
+                }
+            }
+        }
+    }
+
+    {
+        let mut mempool_lock = consensus_engine.mempool.lock();
+        let now = Utc::now().timestamp();
+        for tx in &block.transactions {
+            // Only drop results this block recorded.
+            if get_transaction_result(consensus_engine, &tx.hash).is_none_or(|result| result.block_height != block.header.height) {
+                continue;
+            }
+            let key = format!("tx_result_{}", tx.hash);
+            consensus_engine.db.delete(key.as_bytes()).unwrap();
+
+            // A full pool may turn the transaction away; it can be resubmitted.
+            let _ = mempool_lock.insert(tx.clone(), now);
+        }
+    }
+
+    consensus_engine.persist_neurons();
+}
+
+/// Drops pending items the canonical chain has already included, after a
+/// reorg re-queued those of the blocks it rolled back: executed evidence,
+/// unjail requests whose validator is no longer jailed at that height, and
+/// transactions that have a result or whose nonce is used up.
+pub fn prune_pending(consensus_engine: &ConsensusEngine) {
+    consensus_engine.evidence.lock().retain(|pending| {
+        let key = format!("evidence_{}", hex::encode(evidence_id(pending)));
+        !matches!(consensus_engine.db.get(key.as_bytes()), Ok(Some(_)))
+    });
+
+    {
+        let validators_lock = consensus_engine.validators.lock();
+        consensus_engine.unjails.lock().retain(|pending| {
+            validators_lock
+                .iter()
+                .any(|v| v.address == pending.validator && v.jailed_at == Some(pending.jailed_at))
+        });
+    }
+
+    let executed: Vec<String> = consensus_engine
+        .mempool
+        .lock()
+        .transactions()
+        .map(|pooled| pooled.tx.hash.clone())
+        .filter(|hash| get_transaction_result(consensus_engine, hash).is_some())
+        .collect();
+    let ledger_lock = consensus_engine.ledger.lock();
+    consensus_engine.mempool.lock().retain(|tx| {
+        let account_nonce = ledger_lock.get(&tx.from).map(|account| account.nonce).unwrap_or(0);
+        tx.nonce >= account_nonce && !executed.contains(&tx.hash)
+    });
+}
+
+/// Pays every neuron its share of `reward_pool`, weighted by stake and
+/// maturity, plus the annual staking yield.
+pub fn apply_staking_rewards(
+    neurons: &mut HashMap<u64, Neuron>,
+    reward_pool: u64,
+    annual_yield_percent: f64,
+) {
+    let total_staked: u64 = neurons.values().map(|n| n.staked_amount).sum();
+
+    if total_staked == 0 {
+        return;
+    }
+
+    let total_staked_f64 = total_staked as f64;
+
+    for neuron in neurons.values_mut() {
+        let ratio = neuron.staked_amount as f64 / total_staked_f64;
+        let maturity_bonus = 1.0 + (neuron.maturity as f64 / 100.0);
+        let pool_reward = (reward_pool as f64 * ratio) * maturity_bonus;
+
+        let yield_reward = staking_yield(neuron.staked_amount, annual_yield_percent);
+        let total_reward = pool_reward.round() as u64 + yield_reward;
+
+        neuron.staked_amount += total_reward;
+        neuron.maturity += 1;
+    }
+}
@@ -0,0 +1,373 @@
+use std::sync::Arc;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use hex;
+use serde::{Deserialize, Serialize};
+use crate::core::consensus::block::hash_block;
+use crate::core::consensus::model::ConsensusEngine;
+use crate::core::consensus::tree::{prune_finalized, switch_to_certified};
+use crate::core::consensus::epoch::validator_set_of_block;
+use crate::core::crypto::{sign_data, verify_data};
+use crate::core::types::Address;
+
+const MAX_PENDING_VOTE_BLOCKS: usize = 1024;
+
+/// A validator's signature over a block hash at a given height.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BlockVote {
+    pub block_hash: [u8; 32],
+    pub height: u64,
+    pub validator: Address,
+    pub signature: Vec<u8>,
+}
+
+/// Votes from validators holding more than two thirds of the active stake.
+/// A block with a certificate, and every block below it, is final.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FinalityCertificate {
+    pub block_hash: [u8; 32],
+    pub height: u64,
+    pub votes: Vec<BlockVote>,
+}
+
+fn vote_signing_payload(subnet_id: &str, block_hash: &[u8; 32], height: u64) -> Result<Vec<u8>, String> {
+    bincode::serialize(&(subnet_id, block_hash, height)).map_err(|e| e.to_string())
+}
+
+fn has_supermajority(signed_stake: u64, total_stake: u64) -> bool {
+    total_stake > 0 && (signed_stake as u128) * 3 > (total_stake as u128) * 2
+}
+
+pub fn sign_block_vote(
+    consensus_engine: &ConsensusEngine,
+    block_hash: [u8; 32],
+    height: u64,
+    signing_key: &SigningKey,
+) -> Result<BlockVote, String> {
+    let payload = vote_signing_payload(&consensus_engine.subnet_id, &block_hash, height)?;
+    Ok(BlockVote {
+        block_hash,
+        height,
+        validator: hex::encode(signing_key.verifying_key().to_bytes()),
+        signature: sign_data(signing_key, &payload),
+    })
+}
+
+fn verify_vote(consensus_engine: &ConsensusEngine, vote: &BlockVote) -> Result<(), String> {
+    let pubkey_bytes = hex::decode(&vote.validator)
+        .map_err(|e| format!("Invalid hex address: {}", e))?;
+    let pubkey_array: [u8; 32] = pubkey_bytes
+        .try_into()
+        .map_err(|_| "Invalid length: Expected 32 bytes".to_string())?;
+    let pubkey = VerifyingKey::from_bytes(&pubkey_array)
+        .map_err(|e| format!("Failed to create VerifyingKey: {}", e))?;
+
+    let payload = vote_signing_payload(&consensus_engine.subnet_id, &vote.block_hash, vote.height)?;
+    if !verify_data(&pubkey, &payload, &vote.signature) {
+        return Err(format!("Invalid vote signature from {}", vote.validator));
+    }
+    Ok(())
+}
+
+/// Records a validator's vote. Returns the certificate if this vote pushed the
+/// block over the two-thirds stake threshold.
+pub fn add_vote(
+    consensus_engine: &mut ConsensusEngine,
+    vote: BlockVote,
+) -> Result<Option<FinalityCertificate>, String> {
+    if finalized_height(consensus_engine).is_some_and(|finalized| vote.height <= finalized) {
+        return Err(format!("Vote for height {} at or below the finalized height", vote.height));
+    }
+    let known_height = consensus_engine.tree.lock().get(&vote.block_hash).map(|node| node.height);
+    match known_height {
+        None => return Err("Vote for an unknown block".into()),
+        Some(height) if height != vote.height => {
+            return Err(format!("Vote for height {} names a block at height {}", vote.height, height));
+        }
+        Some(_) => {}
+    }
+
+    if !validator_set_of_block(consensus_engine, &vote.block_hash)?.contains(&vote.validator) {
+        return Err(format!("Vote from {} which is not an active validator", vote.validator));
+    }
+    verify_vote(consensus_engine, &vote)?;
+
+    let block_hash = vote.block_hash;
+    {
+        let mut votes_lock = consensus_engine.votes.lock();
+        if !votes_lock.contains_key(&block_hash) && votes_lock.len() >= MAX_PENDING_VOTE_BLOCKS {
+            return Ok(None);
+        }
+
+        let votes = votes_lock.entry(block_hash).or_default();
+        if votes.iter().any(|existing| existing.validator == vote.validator) {
+            return Ok(None);
+        }
+        votes.push(vote);
+    }
+
+    try_finalize(consensus_engine, &block_hash)
+}
+
+/// Builds a certificate for `block_hash` if the block is on our chain and its
+/// collected votes carry more than two thirds of the active stake.
+pub fn try_finalize(
+    consensus_engine: &mut ConsensusEngine,
+    block_hash: &[u8; 32],
+) -> Result<Option<FinalityCertificate>, String> {
+    let import_lock = Arc::clone(&consensus_engine.import_lock);
+    let _guard = import_lock.lock();
+
+    let Some(height) = block_height(consensus_engine, block_hash) else {
+        return Ok(None);
+    };
+
+    let stakes = validator_set_of_block(consensus_engine, block_hash)?.validators;
+    let total_stake: u64 = stakes.iter().map(|(_, stake)| stake).sum();
+
+    let votes: Vec<BlockVote> = {
+        let votes_lock = consensus_engine.votes.lock();
+        votes_lock
+            .get(block_hash)
+            .map(|votes| votes.iter().filter(|vote| vote.height == height).cloned().collect())
+            .unwrap_or_default()
+    };
+    let signed_stake: u64 = stakes
+        .iter()
+        .filter(|(address, _)| votes.iter().any(|vote| vote.validator == *address))
+        .map(|(_, stake)| stake)
+        .sum();
+
+    if !has_supermajority(signed_stake, total_stake) {
+        return Ok(None);
+    }
+
+    let certificate = FinalityCertificate { block_hash: *block_hash, height, votes };
+    store_certificate(consensus_engine, certificate.clone());
+    Ok(Some(certificate))
+}
+
+/// Checks a certificate received from a peer against the local validator set.
+pub fn verify_certificate(
+    consensus_engine: &ConsensusEngine,
+    certificate: &FinalityCertificate,
+) -> Result<(), String> {
+    let stakes = validator_set_of_block(consensus_engine, &certificate.block_hash)?.validators;
+    let total_stake: u64 = stakes.iter().map(|(_, stake)| stake).sum();
+
+    let mut signers: Vec<&str> = Vec::new();
+    let mut signed_stake: u64 = 0;
+    for vote in &certificate.votes {
+        if vote.block_hash != certificate.block_hash || vote.height != certificate.height {
+            return Err("Certificate contains a vote for a different block".into());
+        }
+        if signers.contains(&vote.validator.as_str()) {
+            continue;
+        }
+
+        let (_, stake) = stakes
+            .iter()
+            .find(|(address, _)| *address == vote.validator)
+            .ok_or_else(|| format!("Certificate vote from {} which is not an active validator", vote.validator))?;
+        verify_vote(consensus_engine, vote)?;
+
+        signers.push(&vote.validator);
+        signed_stake += stake;
+    }
+
+    if !has_supermajority(signed_stake, total_stake) {
+        return Err(format!("Certificate carries {} of {} stake, two thirds required", signed_stake, total_stake));
+    }
+    Ok(())
+}
+
+/// Verifies and stores a certificate for a block in our block tree. A
+/// certified block on another branch overrides fork choice: the head moves
+/// onto its branch before the certificate is stored.
+pub fn import_certificate(
+    consensus_engine: &mut ConsensusEngine,
+    certificate: FinalityCertificate,
+) -> Result<bool, String> {
+    let import_lock = Arc::clone(&consensus_engine.import_lock);
+    let _guard = import_lock.lock();
+
+    if finalized_height(consensus_engine).is_some_and(|finalized| certificate.height <= finalized) {
+        return Ok(false);
+    }
+    let known_height = consensus_engine.tree.lock().get(&certificate.block_hash).map(|node| node.height);
+    if known_height != Some(certificate.height) {
+        return Err("Certificate is for a block we do not know".into());
+    }
+
+    verify_certificate(consensus_engine, &certificate)?;
+    if block_height(consensus_engine, &certificate.block_hash).is_none() {
+        switch_to_certified(consensus_engine, &certificate.block_hash)?;
+    }
+    store_certificate(consensus_engine, certificate);
+    Ok(true)
+}
+
+pub fn finalized_height(consensus_engine: &ConsensusEngine) -> Option<u64> {
+    *consensus_engine.finalized_height.lock()
+}
+
+pub fn get_certificate(consensus_engine: &ConsensusEngine, height: u64) -> Option<FinalityCertificate> {
+    let certificates = consensus_engine.certificates.lock();
+    certificates.get(&height).cloned()
+}
+
+/// Whether `validator` has already voted for `block_hash` (or the block is final).
+pub fn has_voted(consensus_engine: &ConsensusEngine, block_hash: &[u8; 32], validator: &str) -> bool {
+    if let Some(height) = block_height(consensus_engine, block_hash)
+        && finalized_height(consensus_engine).is_some_and(|finalized| height <= finalized)
+    {
+        return true;
+    }
+
+    let votes = consensus_engine.votes.lock();
+    votes.get(block_hash).is_some_and(|votes| votes.iter().any(|vote| vote.validator == validator))
+}
+
+/// Whether this node may vote for `block_hash`: only descendants of the last
+/// block it voted for qualify, until a block at or above that height is final.
+pub fn can_vote_for(consensus_engine: &ConsensusEngine, block_hash: &[u8; 32]) -> bool {
+    let Some(last_vote) = consensus_engine.last_vote.lock().clone() else {
+        return true;
+    };
+    if finalized_height(consensus_engine).is_some_and(|finalized| finalized >= last_vote.height) {
+        return true;
+    }
+    consensus_engine.tree.lock().extends(block_hash, &last_vote.block_hash)
+}
+
+/// Records our own vote so later votes stay on its branch, also across restarts.
+pub fn record_last_vote(consensus_engine: &ConsensusEngine, vote: &BlockVote) {
+    let serialized = bincode::serialize(vote).unwrap();
+    consensus_engine.db.put(b"last_vote", serialized).unwrap();
+    *consensus_engine.last_vote.lock() = Some(vote.clone());
+}
+
+/// Height of `block_hash` if it is on the canonical chain.
+fn block_height(consensus_engine: &ConsensusEngine, block_hash: &[u8; 32]) -> Option<u64> {
+    let chain_lock = consensus_engine.chain.lock();
+    chain_lock.iter().rev().find(|blk| hash_block(blk) == *block_hash).map(|blk| blk.header.height)
+}
+
+fn store_certificate(consensus_engine: &mut ConsensusEngine, certificate: FinalityCertificate) {
+    let height = certificate.height;
+    {
+        let serialized = bincode::serialize(&certificate).unwrap();
+        let key = format!("certificate_{}", height);
+        consensus_engine.db.put(key.as_bytes(), serialized).unwrap();
+        consensus_engine.certificates.lock().insert(height, certificate);
+    }
+
+    {
+        let mut finalized = consensus_engine.finalized_height.lock();
+        if finalized.is_none_or(|current| height > current) {
+            *finalized = Some(height);
+            consensus_engine.db.put(b"finalized_height", height.to_be_bytes()).unwrap();
+        }
+    }
+
+    prune_finalized(consensus_engine, height);
+
+    let chain_lock = consensus_engine.chain.lock();
+    let mut votes = consensus_engine.votes.lock();
+    votes.retain(|hash, _| {
+        chain_lock
+            .iter()
+            .position(|blk| hash_block(blk) == *hash)
+            .is_none_or(|index| index as u64 > height)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::consensus::block::import_block;
+    use crate::core::test_utils::{build_block, signing_key, test_engine};
+
+    fn keys() -> Vec<SigningKey> {
+        (1..=3).map(signing_key).collect()
+    }
+
+    #[test]
+    fn supermajority_needs_more_than_two_thirds() {
+        assert!(!has_supermajority(0, 0));
+        assert!(!has_supermajority(2, 3));
+        assert!(has_supermajority(3, 3));
+        assert!(!has_supermajority(200, 300));
+        assert!(has_supermajority(201, 300));
+        assert!(has_supermajority(u64::MAX, u64::MAX));
+        assert!(!has_supermajority(u64::MAX / 3 * 2, u64::MAX / 3 * 3));
+    }
+
+    #[test]
+    fn votes_of_two_thirds_do_not_finalize() {
+        let keys = keys();
+        let mut engine = test_engine("finality_votes", &keys, 1_000, 0);
+        let block = build_block(&engine, &keys, 1);
+        import_block(&mut engine, block.clone()).unwrap();
+        let block_hash = hash_block(&block);
+
+        for key in &keys[..2] {
+            let vote = sign_block_vote(&engine, block_hash, 0, key).unwrap();
+            assert!(add_vote(&mut engine, vote).unwrap().is_none());
+        }
+        assert_eq!(finalized_height(&engine), None);
+
+        let vote = sign_block_vote(&engine, block_hash, 0, &keys[2]).unwrap();
+        let certificate = add_vote(&mut engine, vote).unwrap().unwrap();
+        assert_eq!(certificate.votes.len(), 3);
+        assert_eq!(finalized_height(&engine), Some(0));
+        assert!(verify_certificate(&engine, &certificate).is_ok());
+    }
+
+    #[test]
+    fn certificate_without_supermajority_is_rejected() {
+        let keys = keys();
+        let mut engine = test_engine("finality_short", &keys, 1_000, 0);
+        let block = build_block(&engine, &keys, 1);
+        import_block(&mut engine, block.clone()).unwrap();
+        let block_hash = hash_block(&block);
+
+        let votes = keys[..2]
+            .iter()
+            .chain(&keys[..1])
+            .map(|key| sign_block_vote(&engine, block_hash, 0, key).unwrap())
+            .collect();
+        let certificate = FinalityCertificate { block_hash, height: 0, votes };
+        assert!(verify_certificate(&engine, &certificate).is_err());
+        assert!(import_certificate(&mut engine, certificate).is_err());
+        assert_eq!(finalized_height(&engine), None);
+    }
+
+    #[test]
+    fn certificate_moves_head_onto_certified_branch() {
+        let keys = keys();
+        let mut engine = test_engine("finality_first", &keys, 1_000, 0);
+        let mut other = test_engine("finality_second", &keys, 1_000, 0);
+
+        let genesis = build_block(&engine, &keys, 1);
+        import_block(&mut engine, genesis.clone()).unwrap();
+        import_block(&mut other, genesis).unwrap();
+
+        let ours = build_block(&engine, &keys, 2);
+        import_block(&mut engine, ours.clone()).unwrap();
+        let theirs = build_block(&other, &keys, 3);
+        import_block(&mut engine, theirs.clone()).unwrap();
+
+        // Equal length, so fork choice keeps our block until the certificate.
+        let tip = |engine: &ConsensusEngine| engine.chain.lock().last().map(hash_block).unwrap();
+        assert_eq!(tip(&engine), hash_block(&ours));
+
+        let block_hash = hash_block(&theirs);
+        let votes = keys.iter().map(|key| sign_block_vote(&other, block_hash, 1, key).unwrap()).collect();
+        let certificate = FinalityCertificate { block_hash, height: 1, votes };
+        assert!(import_certificate(&mut engine, certificate).unwrap());
+
+        assert_eq!(tip(&engine), block_hash);
+        assert_eq!(finalized_height(&engine), Some(1));
+        assert!(!engine.tree.lock().contains(&hash_block(&ours)));
+    }
+}
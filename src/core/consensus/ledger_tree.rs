@@ -0,0 +1,387 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::OnceLock;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::core::consensus::block::hash_block;
+use crate::core::consensus::finality::finalized_height;
+use crate::core::consensus::model::{Account, ConsensusEngine};
+
+/// Depth of the sparse Merkle tree: one level per bit of `sha256(address)`.
+pub const TREE_DEPTH: usize = 256;
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Merkle path for one address. `siblings[0]` is the sibling next to the leaf
+/// and `None` stands for the root of an empty subtree at that level.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AccountProof {
+    pub address: String,
+    pub account: Option<Account>,
+    pub siblings: Vec<Option<[u8; 32]>>,
+}
+
+/// Account proof anchored at the most recent finalized block, so it can be
+/// checked against that block's `ledger_root` alone.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FinalizedAccountProof {
+    pub height: u64,
+    pub block_hash: [u8; 32],
+    pub ledger_root: [u8; 32],
+    pub proof: AccountProof,
+}
+
+/// The sparse Merkle tree over every account, kept up to date as accounts
+/// change instead of being rebuilt for each root or proof. Leaves are
+/// `sha256(0x00 || key || bincode(account))` keyed by `sha256(address)`,
+/// inner nodes `sha256(0x01 || left || right)`; an empty leaf is all zeroes.
+/// Only inner nodes with at least two leaves below them are cached; a subtree
+/// holding a single leaf is hashed from that leaf when needed.
+///
+/// Accounts marked local were seeded or changed outside block execution on
+/// this node alone (`init_ledger`, staking), so other nodes cannot agree on
+/// their balance. They are kept out of the tree and out of `ledger_root`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LedgerTree {
+    leaves: BTreeMap<[u8; 32], [u8; 32]>,
+    nodes: HashMap<(usize, [u8; 32]), [u8; 32]>,
+    local: BTreeSet<String>,
+}
+
+impl LedgerTree {
+    pub fn from_ledger(ledger: &HashMap<String, Account>, local: BTreeSet<String>) -> Self {
+        let mut tree = Self { local, ..Self::default() };
+        for (address, account) in ledger {
+            tree.update(address, Some(account));
+        }
+        tree
+    }
+
+    /// Sets the leaf of `address` to `account`, or clears it, and rehashes
+    /// the path from that leaf to the root. Local accounts stay empty.
+    pub fn update(&mut self, address: &str, account: Option<&Account>) {
+        let key = account_key(address);
+        match account.filter(|_| !self.local.contains(address)) {
+            Some(account) => self.leaves.insert(key, leaf_hash(&key, account)),
+            None => self.leaves.remove(&key),
+        };
+
+        for depth in (0..TREE_DEPTH).rev() {
+            let node = prefix(&key, depth);
+            if self.leaf_count(depth, &node) < 2 {
+                self.nodes.remove(&(depth, node));
+                continue;
+            }
+            let left = self.subtree_root(depth + 1, &node);
+            let right = self.subtree_root(depth + 1, &with_bit(&node, depth));
+            self.nodes.insert((depth, node), node_hash(&left, &right));
+        }
+    }
+
+    /// Takes `address` out of the tree for good.
+    pub fn mark_local(&mut self, address: &str) {
+        if self.local.insert(address.to_string()) {
+            self.update(address, None);
+        }
+    }
+
+    pub fn is_local(&self, address: &str) -> bool {
+        self.local.contains(address)
+    }
+
+    pub fn local_accounts(&self) -> &BTreeSet<String> {
+        &self.local
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.subtree_root(0, &[0u8; 32])
+    }
+
+    /// Merkle path for `address`, whether or not the account exists.
+    pub fn siblings(&self, address: &str) -> Vec<Option<[u8; 32]>> {
+        let key = account_key(address);
+        (0..TREE_DEPTH)
+            .rev()
+            .map(|depth| {
+                let parent = prefix(&key, depth);
+                let sibling = if bit(&key, depth) { parent } else { with_bit(&parent, depth) };
+                (self.leaf_count(depth + 1, &sibling) > 0).then(|| self.subtree_root(depth + 1, &sibling))
+            })
+            .collect()
+    }
+
+    /// Leaves below the node at `depth` with key prefix `node`, capped at two.
+    fn leaf_count(&self, depth: usize, node: &[u8; 32]) -> usize {
+        self.leaves.range(*node..=last_key(node, depth)).take(2).count()
+    }
+
+    fn subtree_root(&self, depth: usize, node: &[u8; 32]) -> [u8; 32] {
+        if let Some(hash) = self.nodes.get(&(depth, *node)) {
+            return *hash;
+        }
+        match self.leaves.range(*node..=last_key(node, depth)).next() {
+            Some((key, leaf)) => single_leaf_root(key, leaf, depth),
+            None => empty_root(depth),
+        }
+    }
+}
+
+/// Root of a tree holding every account in `ledger`.
+pub fn ledger_root(ledger: &HashMap<String, Account>) -> [u8; 32] {
+    LedgerTree::from_ledger(ledger, BTreeSet::new()).root()
+}
+
+/// Builds an inclusion proof for `address`, or a non-inclusion proof if the
+/// account does not exist or is local to this node.
+pub fn prove_account(tree: &LedgerTree, ledger: &HashMap<String, Account>, address: &str) -> AccountProof {
+    AccountProof {
+        address: address.to_string(),
+        account: ledger.get(address).filter(|_| !tree.is_local(address)).cloned(),
+        siblings: tree.siblings(address),
+    }
+}
+
+/// Proves `address` against the finalized ledger. The ledger at the finalized
+/// height is rebuilt by undoing the canonical blocks above it, tip first.
+pub fn prove_finalized_account(consensus_engine: &ConsensusEngine, address: &str) -> Result<FinalizedAccountProof, String> {
+    let height = finalized_height(consensus_engine).ok_or("No block has been finalized yet")?;
+
+    let (ledger, tree, finalized_block) = {
+        let chain_lock = consensus_engine.chain.lock();
+        let undo_lock = consensus_engine.undo.lock();
+        let mut ledger = consensus_engine.ledger.lock().clone();
+        let mut tree = consensus_engine.ledger_tree.lock().clone();
+
+        for blk in chain_lock.iter().skip(height as usize + 1).rev() {
+            let undo = undo_lock
+                .get(&hash_block(blk))
+                .ok_or("Missing undo record for canonical block")?;
+            for (address, account) in undo.ledger.iter().rev() {
+                match account {
+                    Some(account) => ledger.insert(address.clone(), account.clone()),
+                    None => ledger.remove(address),
+                };
+                tree.update(address, account.as_ref());
+            }
+        }
+
+        let finalized_block = chain_lock.get(height as usize).cloned().ok_or("Finalized block is not on the chain")?;
+        (ledger, tree, finalized_block)
+    };
+
+    if tree.root() != finalized_block.header.ledger_root {
+        return Err("Ledger state does not match the finalized ledger root".into());
+    }
+
+    Ok(FinalizedAccountProof {
+        height,
+        block_hash: hash_block(&finalized_block),
+        ledger_root: finalized_block.header.ledger_root,
+        proof: prove_account(&tree, &ledger, address),
+    })
+}
+
+/// Checks `proof` against a ledger root taken from a block header. Returns
+/// `false` for malformed proofs or if the account data does not match.
+pub fn verify_account_proof(root: &[u8; 32], proof: &AccountProof) -> bool {
+    if proof.siblings.len() != TREE_DEPTH {
+        return false;
+    }
+    if proof.account.as_ref().is_some_and(|account| account.address != proof.address) {
+        return false;
+    }
+
+    let key = account_key(&proof.address);
+    let mut hash = match &proof.account {
+        Some(account) => leaf_hash(&key, account),
+        None => empty_root(TREE_DEPTH),
+    };
+
+    for (level, sibling) in proof.siblings.iter().enumerate() {
+        let depth = TREE_DEPTH - 1 - level;
+        let sibling = sibling.unwrap_or_else(|| empty_root(depth + 1));
+        hash = if bit(&key, depth) {
+            node_hash(&sibling, &hash)
+        } else {
+            node_hash(&hash, &sibling)
+        };
+    }
+
+    hash == *root
+}
+
+pub fn account_key(address: &str) -> [u8; 32] {
+    Sha256::digest(address.as_bytes()).into()
+}
+
+fn leaf_hash(key: &[u8; 32], account: &Account) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(key);
+    hasher.update(bincode::serialize(account).unwrap());
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Root of an empty subtree whose top sits at `depth` (`TREE_DEPTH` is a leaf).
+fn empty_root(depth: usize) -> [u8; 32] {
+    static EMPTY: OnceLock<Vec<[u8; 32]>> = OnceLock::new();
+    let empty = EMPTY.get_or_init(|| {
+        let mut roots = vec![[0u8; 32]; TREE_DEPTH + 1];
+        for depth in (0..TREE_DEPTH).rev() {
+            roots[depth] = node_hash(&roots[depth + 1], &roots[depth + 1]);
+        }
+        roots
+    });
+    empty[depth]
+}
+
+/// Root of a subtree at `depth` whose only leaf is `leaf` under `key`.
+fn single_leaf_root(key: &[u8; 32], leaf: &[u8; 32], depth: usize) -> [u8; 32] {
+    let mut hash = *leaf;
+    for level in (depth..TREE_DEPTH).rev() {
+        let sibling = empty_root(level + 1);
+        hash = if bit(key, level) {
+            node_hash(&sibling, &hash)
+        } else {
+            node_hash(&hash, &sibling)
+        };
+    }
+    hash
+}
+
+fn bit(key: &[u8; 32], depth: usize) -> bool {
+    key[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+/// `key` with every bit from `depth` on cleared: the first key under the node
+/// at `depth` on the path to `key`.
+fn prefix(key: &[u8; 32], depth: usize) -> [u8; 32] {
+    let mut node = [0u8; 32];
+    for index in 0..depth {
+        if bit(key, index) {
+            node[index / 8] |= 0x80 >> (index % 8);
+        }
+    }
+    node
+}
+
+/// Last key under the node at `depth` whose first key is `node`.
+fn last_key(node: &[u8; 32], depth: usize) -> [u8; 32] {
+    let mut key = *node;
+    for index in depth..TREE_DEPTH {
+        key[index / 8] |= 0x80 >> (index % 8);
+    }
+    key
+}
+
+fn with_bit(node: &[u8; 32], depth: usize) -> [u8; 32] {
+    let mut node = *node;
+    node[depth / 8] |= 0x80 >> (depth % 8);
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_utils::{address_of, signing_key};
+    use ed25519_dalek::VerifyingKey;
+
+    fn account(seed: u8, balance: u64) -> Account {
+        let key = signing_key(seed);
+        Account {
+            address: address_of(&key),
+            public_key: VerifyingKey::from(&key),
+            balance,
+            nonce: 0,
+        }
+    }
+
+    fn ledger(accounts: &[Account]) -> HashMap<String, Account> {
+        accounts.iter().map(|account| (account.address.clone(), account.clone())).collect()
+    }
+
+    #[test]
+    fn inclusion_proof_verifies_against_root() {
+        let ledger = ledger(&[account(1, 100), account(2, 200), account(3, 300)]);
+        let tree = LedgerTree::from_ledger(&ledger, BTreeSet::new());
+        let root = tree.root();
+
+        for address in ledger.keys() {
+            let proof = prove_account(&tree, &ledger, address);
+            assert!(proof.account.is_some());
+            assert!(verify_account_proof(&root, &proof));
+        }
+    }
+
+    #[test]
+    fn tampered_inclusion_proof_fails() {
+        let ledger = ledger(&[account(1, 100), account(2, 200)]);
+        let tree = LedgerTree::from_ledger(&ledger, BTreeSet::new());
+        let mut proof = prove_account(&tree, &ledger, &account(1, 0).address);
+
+        proof.account.as_mut().unwrap().balance += 1;
+        assert!(!verify_account_proof(&tree.root(), &proof));
+    }
+
+    #[test]
+    fn non_inclusion_proof_verifies_for_missing_account() {
+        let ledger = ledger(&[account(1, 100), account(2, 200)]);
+        let tree = LedgerTree::from_ledger(&ledger, BTreeSet::new());
+        let missing = account(9, 0).address;
+
+        let proof = prove_account(&tree, &ledger, &missing);
+        assert!(proof.account.is_none());
+        assert!(verify_account_proof(&tree.root(), &proof));
+
+        // Claiming the missing account exists must not verify.
+        let mut forged = proof.clone();
+        forged.account = Some(account(9, 50));
+        assert!(!verify_account_proof(&tree.root(), &forged));
+    }
+
+    #[test]
+    fn non_inclusion_proof_fails_for_existing_account() {
+        let ledger = ledger(&[account(1, 100), account(2, 200)]);
+        let tree = LedgerTree::from_ledger(&ledger, BTreeSet::new());
+
+        let mut proof = prove_account(&tree, &ledger, &account(1, 0).address);
+        proof.account = None;
+        assert!(!verify_account_proof(&tree.root(), &proof));
+    }
+
+    #[test]
+    fn incremental_updates_match_rebuilt_tree() {
+        let mut accounts = ledger(&[account(1, 100), account(2, 200), account(3, 300)]);
+        let mut tree = LedgerTree::from_ledger(&accounts, BTreeSet::new());
+
+        let changed = account(2, 250);
+        tree.update(&changed.address, Some(&changed));
+        accounts.insert(changed.address.clone(), changed);
+        let removed = account(3, 0).address;
+        tree.update(&removed, None);
+        accounts.remove(&removed);
+
+        assert_eq!(tree.root(), ledger_root(&accounts));
+    }
+
+    #[test]
+    fn local_accounts_stay_out_of_the_tree() {
+        let shared = account(1, 100);
+        let local = account(2, 200);
+        let ledger = ledger(&[shared.clone(), local.clone()]);
+        let tree = LedgerTree::from_ledger(&ledger, BTreeSet::from([local.address.clone()]));
+
+        assert_eq!(tree.root(), ledger_root(&HashMap::from([(shared.address.clone(), shared)])));
+        let proof = prove_account(&tree, &ledger, &local.address);
+        assert!(proof.account.is_none());
+        assert!(verify_account_proof(&tree.root(), &proof));
+    }
+}
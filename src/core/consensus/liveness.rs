@@ -0,0 +1,226 @@
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use hex;
+use crate::core::consensus::crypto_hash;
+use crate::core::consensus::model::{ConsensusConfig, ConsensusEngine};
+use crate::core::consensus::validator::{ValidatorInfo, select_leader};
+use crate::core::crypto::{sign_data, verify_data};
+use crate::core::types::{Address, Block, Unjail};
+
+/// Most unjail requests a single block may carry.
+pub const MAX_UNJAILS_PER_BLOCK: usize = 16;
+
+/// Slots between `block`'s parent and `block` that passed without a block,
+/// each with the leader that should have filled it. Only the last
+/// `liveness_window` slots are considered, since older misses would be
+/// dropped from the window right away.
+pub fn missed_slots(consensus_engine: &ConsensusEngine, block: &Block) -> Vec<(u64, Address)> {
+    let parent_slot = {
+        let tree_lock = consensus_engine.tree.lock();
+        tree_lock.get(&block.header.parent_hash).map(|parent| parent.block.header.slot)
+    };
+    let first_slot = parent_slot
+        .map(|slot| slot + 1)
+        .unwrap_or(0)
+        .max(block.header.slot.saturating_sub(consensus_engine.config.liveness_window));
+
+    (first_slot..block.header.slot)
+        .filter_map(|slot| {
+            select_leader(consensus_engine, &block.header.parent_hash, block.header.height, slot)
+                .map(|leader| (slot, leader))
+        })
+        .collect()
+}
+
+/// Counts the block's producer and the leaders of the slots it skipped, and
+/// jails every validator that missed `max_missed_slots` of the slots in the
+/// last `liveness_window`. The producer has just shown it is live, so its
+/// recent misses are cleared instead; otherwise a lone validator returning
+/// from an outage would jail itself and halt the chain. Returns the
+/// validators jailed by this block.
+pub fn record_liveness(
+    config: &ConsensusConfig,
+    validators: &mut [ValidatorInfo],
+    block: &Block,
+    missed: &[(u64, Address)],
+) -> Vec<Address> {
+    let window_start = block.header.slot.saturating_sub(config.liveness_window);
+    let mut jailed = Vec::new();
+
+    for validator in validators.iter_mut() {
+        for (slot, _) in missed.iter().filter(|(_, leader)| *leader == validator.address) {
+            validator.missed_slots += 1;
+            validator.recent_misses.push(*slot);
+        }
+        validator.recent_misses.retain(|slot| *slot >= window_start);
+        if validator.address == block.header.validator {
+            validator.produced_slots += 1;
+            validator.recent_misses.clear();
+        }
+
+        if validator.active
+            && validator.jailed_at.is_none()
+            && validator.recent_misses.len() as u64 >= config.max_missed_slots
+        {
+            validator.active = false;
+            validator.jailed_at = Some(block.header.height);
+            jailed.push(validator.address.clone());
+        }
+    }
+
+    jailed
+}
+
+/// Restores the validators named by the block's unjail requests. A request
+/// whose validator is no longer jailed at that height is ignored.
+pub fn apply_unjails(validators: &mut [ValidatorInfo], unjails: &[Unjail]) {
+    for unjail in unjails {
+        if let Some(validator) = validators
+            .iter_mut()
+            .find(|v| v.address == unjail.validator && v.jailed_at == Some(unjail.jailed_at))
+        {
+            validator.active = true;
+            validator.jailed_at = None;
+            validator.recent_misses.clear();
+        }
+    }
+}
+
+/// Commitment to a block's unjail requests stored in `BlockHeader::unjail_hash`.
+pub fn unjail_hash(unjails: &[Unjail]) -> [u8; 32] {
+    if unjails.is_empty() {
+        return [0u8; 32];
+    }
+    crypto_hash(&bincode::serialize(unjails).unwrap_or_default())
+}
+
+fn serialize_unjail_for_signing(unjail: &Unjail) -> Result<Vec<u8>, String> {
+    let mut unsigned = unjail.clone();
+    unsigned.signature.clear();
+    bincode::serialize(&unsigned).map_err(|e| format!("Unjail serialization error: {}", e))
+}
+
+/// Signs a request to unjail the validator owned by `signing_key`.
+pub fn sign_unjail(consensus_engine: &ConsensusEngine, signing_key: &SigningKey) -> Result<Unjail, String> {
+    let address = hex::encode(signing_key.verifying_key().to_bytes());
+    let jailed_at = consensus_engine
+        .validators
+        .lock()
+        .iter()
+        .find(|validator| validator.address == address)
+        .ok_or("Not a registered validator")?
+        .jailed_at
+        .ok_or("Validator is not jailed")?;
+
+    let mut unjail = Unjail {
+        subnet_id: consensus_engine.subnet_id.clone(),
+        validator: address,
+        jailed_at,
+        signature: vec![],
+    };
+    let signable = serialize_unjail_for_signing(&unjail)?;
+    unjail.signature = sign_data(signing_key, &signable);
+    Ok(unjail)
+}
+
+/// Checks that the request is signed by a validator that is currently jailed
+/// at the height it names.
+pub fn verify_unjail(consensus_engine: &ConsensusEngine, unjail: &Unjail) -> Result<(), String> {
+    if unjail.subnet_id != consensus_engine.subnet_id {
+        return Err("Unjail request belongs to another subnet".into());
+    }
+
+    let jailed_at = consensus_engine
+        .validators
+        .lock()
+        .iter()
+        .find(|validator| validator.address == unjail.validator)
+        .ok_or_else(|| format!("Unjail request names unknown validator {}", unjail.validator))?
+        .jailed_at;
+    if jailed_at != Some(unjail.jailed_at) {
+        return Err(format!("Validator {} is not jailed at height {}", unjail.validator, unjail.jailed_at));
+    }
+
+    let pubkey_bytes: [u8; 32] = hex::decode(&unjail.validator)
+        .map_err(|e| format!("Invalid validator address: {}", e))?
+        .try_into()
+        .map_err(|_| "Invalid validator address length".to_string())?;
+    let pubkey = VerifyingKey::from_bytes(&pubkey_bytes)
+        .map_err(|e| format!("Invalid validator public key: {}", e))?;
+
+    let signable = serialize_unjail_for_signing(unjail)?;
+    if !verify_data(&pubkey, &signable, &unjail.signature) {
+        return Err("Unjail request signature does not verify".into());
+    }
+
+    Ok(())
+}
+
+/// First height at which a validator jailed at `jailed_at` may be unjailed.
+pub fn unjail_height(consensus_engine: &ConsensusEngine, jailed_at: u64) -> u64 {
+    jailed_at + consensus_engine.config.unjail_cooldown
+}
+
+/// Verifies `unjail` and adds it to the pending pool. Returns `false` if it is
+/// already pending.
+pub fn add_unjail(consensus_engine: &mut ConsensusEngine, unjail: Unjail) -> Result<bool, String> {
+    verify_unjail(consensus_engine, &unjail)?;
+
+    let mut pending_lock = consensus_engine.unjails.lock();
+    if pending_lock.contains(&unjail) {
+        return Ok(false);
+    }
+    pending_lock.push(unjail);
+    Ok(true)
+}
+
+/// Pending unjail requests a block at `height` may carry: still valid and past
+/// their cooldown.
+pub fn pending_unjails(consensus_engine: &ConsensusEngine, height: u64) -> Vec<Unjail> {
+    let pending: Vec<Unjail> = consensus_engine.unjails.lock().clone();
+    pending
+        .into_iter()
+        .filter(|unjail| height >= unjail_height(consensus_engine, unjail.jailed_at))
+        .filter(|unjail| verify_unjail(consensus_engine, unjail).is_ok())
+        .take(MAX_UNJAILS_PER_BLOCK)
+        .collect()
+}
+
+/// Checks the unjail requests a block carries before it is executed.
+pub fn validate_block_unjails(consensus_engine: &ConsensusEngine, block: &Block) -> Result<(), String> {
+    if block.unjails.len() > MAX_UNJAILS_PER_BLOCK {
+        return Err(format!(
+            "Block carries {} unjail requests, limit is {}",
+            block.unjails.len(),
+            MAX_UNJAILS_PER_BLOCK
+        ));
+    }
+
+    let mut seen: Vec<&Address> = Vec::new();
+    for unjail in &block.unjails {
+        verify_unjail(consensus_engine, unjail)?;
+
+        let ready_at = unjail_height(consensus_engine, unjail.jailed_at);
+        if block.header.height < ready_at {
+            return Err(format!(
+                "Validator {} cannot be unjailed before height {}",
+                unjail.validator, ready_at
+            ));
+        }
+        if seen.contains(&&unjail.validator) {
+            return Err("Block unjails the same validator twice".into());
+        }
+        seen.push(&unjail.validator);
+    }
+
+    Ok(())
+}
+
+/// Liveness counters and jail status of `address`.
+pub fn get_liveness(consensus_engine: &ConsensusEngine, address: &str) -> Option<ValidatorInfo> {
+    consensus_engine
+        .validators
+        .lock()
+        .iter()
+        .find(|validator| validator.address == address)
+        .cloned()
+}
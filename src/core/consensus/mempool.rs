@@ -0,0 +1,367 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use serde::{Deserialize, Serialize};
+use crate::core::consensus::model::Account;
+use crate::core::types::Transaction;
+
+/// Limits read from the `[mempool]` config section. They are local policy and
+/// may differ between nodes.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MempoolConfig {
+    pub max_transactions: usize,
+    pub max_bytes: usize,
+    pub ttl_secs: i64,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        Self {
+            max_transactions: 10_000,
+            max_bytes: 16 * 1024 * 1024,
+            ttl_secs: 3600,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PooledTransaction {
+    pub tx: Transaction,
+    pub received_at: i64,
+    pub size: usize,
+}
+
+/// Pending transactions indexed by hash and queued per sender in nonce order.
+/// When the pool is full the cheapest transaction that ends a sender's queue
+/// is evicted, so eviction never opens a nonce gap.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Mempool {
+    pub config: MempoolConfig,
+    by_hash: HashMap<String, PooledTransaction>,
+    by_sender: HashMap<String, BTreeMap<u64, String>>,
+    total_bytes: usize,
+}
+
+impl Mempool {
+    pub fn new(config: MempoolConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_hash.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_hash.is_empty()
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.by_hash.contains_key(hash)
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&Transaction> {
+        self.by_hash.get(hash).map(|pooled| &pooled.tx)
+    }
+
+    pub fn has_nonce(&self, sender: &str, nonce: u64) -> bool {
+        self.by_sender.get(sender).is_some_and(|queue| queue.contains_key(&nonce))
+    }
+
+    pub fn transactions(&self) -> impl Iterator<Item = &PooledTransaction> {
+        self.by_hash.values()
+    }
+
+    pub fn hashes(&self) -> Vec<String> {
+        self.by_hash.keys().cloned().collect()
+    }
+
+    /// Pending transactions from `sender`, lowest nonce first.
+    pub fn by_sender(&self, sender: &str) -> Vec<Transaction> {
+        self.by_sender
+            .get(sender)
+            .map(|queue| queue.values().filter_map(|hash| self.get(hash).cloned()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Amount plus fee of everything `sender` has queued.
+    pub fn pending_outgoing(&self, sender: &str) -> u64 {
+        self.by_sender(sender)
+            .iter()
+            .map(|tx| tx.amount.saturating_add(tx.fee))
+            .sum()
+    }
+
+    /// Adds a transaction that already passed admission checks, evicting
+    /// cheaper transactions if the pool is over its limits.
+    pub fn insert(&mut self, tx: Transaction, received_at: i64) -> Result<(), String> {
+        let size = bincode::serialized_size(&tx).map_err(|e| e.to_string())? as usize;
+        self.insert_pooled(PooledTransaction { tx, received_at, size })
+    }
+
+    pub fn insert_pooled(&mut self, pooled: PooledTransaction) -> Result<(), String> {
+        if self.contains(&pooled.tx.hash) {
+            return Err(format!("Transaction {} already in mempool", pooled.tx.hash));
+        }
+        if self.has_nonce(&pooled.tx.from, pooled.tx.nonce) {
+            return Err(format!(
+                "A transaction with nonce {} from {} is already queued",
+                pooled.tx.nonce, pooled.tx.from
+            ));
+        }
+        if pooled.size > self.config.max_bytes {
+            return Err(format!("Transaction of {} bytes exceeds the mempool size limit", pooled.size));
+        }
+
+        while self.len() + 1 > self.config.max_transactions || self.total_bytes + pooled.size > self.config.max_bytes {
+            let Some((victim_hash, victim_fee)) = self.eviction_candidate(&pooled.tx.from) else {
+                return Err("Mempool is full".to_string());
+            };
+            if victim_fee >= pooled.tx.fee {
+                return Err(format!("Mempool is full and transaction fee {} is too low", pooled.tx.fee));
+            }
+            self.remove(&victim_hash);
+        }
+
+        self.total_bytes += pooled.size;
+        self.by_sender
+            .entry(pooled.tx.from.clone())
+            .or_default()
+            .insert(pooled.tx.nonce, pooled.tx.hash.clone());
+        self.by_hash.insert(pooled.tx.hash.clone(), pooled);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, hash: &str) -> Option<Transaction> {
+        let pooled = self.by_hash.remove(hash)?;
+        self.total_bytes -= pooled.size;
+
+        if let Some(queue) = self.by_sender.get_mut(&pooled.tx.from) {
+            queue.remove(&pooled.tx.nonce);
+            if queue.is_empty() {
+                self.by_sender.remove(&pooled.tx.from);
+            }
+        }
+        Some(pooled.tx)
+    }
+
+    pub fn clear(&mut self) {
+        self.by_hash.clear();
+        self.by_sender.clear();
+        self.total_bytes = 0;
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&Transaction) -> bool) {
+        let dropped: Vec<String> = self
+            .by_hash
+            .values()
+            .filter(|pooled| !keep(&pooled.tx))
+            .map(|pooled| pooled.tx.hash.clone())
+            .collect();
+        for hash in dropped {
+            self.remove(&hash);
+        }
+    }
+
+    /// Drops transactions that have waited longer than the configured TTL.
+    pub fn expire(&mut self, now: i64) -> usize {
+        let ttl_secs = self.config.ttl_secs;
+        let before = self.len();
+        let expired: Vec<String> = self
+            .by_hash
+            .values()
+            .filter(|pooled| now - pooled.received_at > ttl_secs)
+            .map(|pooled| pooled.tx.hash.clone())
+            .collect();
+        for hash in expired {
+            self.remove(&hash);
+        }
+        before - self.len()
+    }
+
+    /// Transactions that can run on top of `ledger`, highest fee first while
+    /// keeping every sender's nonces consecutive from its account nonce.
+    pub fn executable(&self, ledger: &HashMap<String, Account>) -> Vec<Transaction> {
+        let mut heads = BinaryHeap::new();
+        for (sender, queue) in &self.by_sender {
+            let account_nonce = ledger.get(sender).map(|account| account.nonce).unwrap_or(0);
+            if let Some(hash) = queue.get(&account_nonce) {
+                heads.push(Candidate::new(&self.by_hash[hash].tx));
+            }
+        }
+
+        let mut selected = Vec::new();
+        while let Some(Candidate { tx, .. }) = heads.pop() {
+            if let Some(hash) = self.by_sender[&tx.from].get(&(tx.nonce + 1)) {
+                heads.push(Candidate::new(&self.by_hash[hash].tx));
+            }
+            selected.push(tx.clone());
+        }
+        selected
+    }
+
+    /// Cheapest transaction at the end of a sender's queue, skipping `sender`
+    /// itself so a new transaction never evicts its own predecessor.
+    fn eviction_candidate(&self, sender: &str) -> Option<(String, u64)> {
+        self.by_sender
+            .iter()
+            .filter(|(from, _)| from.as_str() != sender)
+            .filter_map(|(_, queue)| queue.values().next_back())
+            .map(|hash| (hash.clone(), self.by_hash[hash].tx.fee))
+            .min_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)))
+    }
+}
+
+/// Heap entry ordering ready transactions by fee, then hash for determinism.
+struct Candidate<'a> {
+    fee: u64,
+    tx: &'a Transaction,
+}
+
+impl<'a> Candidate<'a> {
+    fn new(tx: &'a Transaction) -> Self {
+        Self { fee: tx.fee, tx }
+    }
+}
+
+impl Ord for Candidate<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.fee.cmp(&other.fee).then_with(|| other.tx.hash.cmp(&self.tx.hash))
+    }
+}
+
+impl PartialOrd for Candidate<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use ed25519_dalek::VerifyingKey;
+    use crate::core::test_utils::{address_of, signing_key};
+    use crate::core::types::{TransactionStatus, TransactionType};
+
+    fn tx(sender: u8, nonce: u64, fee: u64) -> Transaction {
+        Transaction {
+            hash: format!("{:02x}-{}-{}", sender, nonce, fee),
+            r#type: TransactionType::Transfer,
+            status: TransactionStatus::Pending,
+            index: 0,
+            nonce,
+            timestamp: Utc::now(),
+            from: address_of(&signing_key(sender)),
+            to: address_of(&signing_key(0)),
+            amount: 1,
+            fee,
+            memo: 0,
+            nrc_memo: 0,
+            signature: vec![],
+        }
+    }
+
+    fn account(sender: u8, nonce: u64) -> (String, Account) {
+        let key = signing_key(sender);
+        let account = Account {
+            address: address_of(&key),
+            public_key: VerifyingKey::from(&key),
+            balance: 1_000,
+            nonce,
+        };
+        (account.address.clone(), account)
+    }
+
+    fn mempool(max_transactions: usize) -> Mempool {
+        Mempool::new(MempoolConfig {
+            max_transactions,
+            ttl_secs: 60,
+            ..MempoolConfig::default()
+        })
+    }
+
+    #[test]
+    fn full_pool_evicts_cheapest_queue_tail() {
+        let mut pool = mempool(3);
+        pool.insert(tx(1, 0, 5), 0).unwrap();
+        pool.insert(tx(1, 1, 1), 0).unwrap();
+        pool.insert(tx(2, 0, 3), 0).unwrap();
+
+        pool.insert(tx(3, 0, 4), 0).unwrap();
+        assert_eq!(pool.len(), 3);
+        assert!(!pool.contains(&tx(1, 1, 1).hash));
+        assert!(pool.contains(&tx(1, 0, 5).hash));
+    }
+
+    #[test]
+    fn full_pool_rejects_fee_not_above_cheapest() {
+        let mut pool = mempool(2);
+        pool.insert(tx(1, 0, 3), 0).unwrap();
+        pool.insert(tx(2, 0, 3), 0).unwrap();
+
+        assert!(pool.insert(tx(3, 0, 3), 0).is_err());
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn eviction_never_removes_own_predecessor() {
+        let mut pool = mempool(2);
+        pool.insert(tx(1, 0, 1), 0).unwrap();
+        pool.insert(tx(1, 1, 1), 0).unwrap();
+
+        assert!(pool.insert(tx(1, 2, 10), 0).is_err());
+        assert_eq!(pool.by_sender(&tx(1, 0, 1).from).len(), 2);
+    }
+
+    #[test]
+    fn expire_drops_transactions_past_ttl() {
+        let mut pool = mempool(10);
+        pool.insert(tx(1, 0, 1), 0).unwrap();
+        pool.insert(tx(2, 0, 1), 50).unwrap();
+
+        assert_eq!(pool.expire(60), 0);
+        assert_eq!(pool.expire(61), 1);
+        assert!(!pool.contains(&tx(1, 0, 1).hash));
+        assert!(pool.contains(&tx(2, 0, 1).hash));
+        assert_eq!(pool.size_bytes(), pool.transactions().map(|pooled| pooled.size).sum::<usize>());
+    }
+
+    #[test]
+    fn future_nonces_wait_for_the_gap_to_fill() {
+        let mut pool = mempool(10);
+        let ledger: HashMap<String, Account> = [account(1, 0)].into_iter().collect();
+        pool.insert(tx(1, 1, 9), 0).unwrap();
+        pool.insert(tx(1, 2, 9), 0).unwrap();
+        assert!(pool.executable(&ledger).is_empty());
+
+        pool.insert(tx(1, 0, 1), 0).unwrap();
+        let nonces: Vec<u64> = pool.executable(&ledger).iter().map(|tx| tx.nonce).collect();
+        assert_eq!(nonces, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn executable_orders_senders_by_fee() {
+        let mut pool = mempool(10);
+        let ledger: HashMap<String, Account> = [account(1, 0), account(2, 4)].into_iter().collect();
+        pool.insert(tx(1, 0, 2), 0).unwrap();
+        pool.insert(tx(2, 4, 7), 0).unwrap();
+        pool.insert(tx(2, 3, 50), 0).unwrap();
+
+        let selected: Vec<(u64, u64)> = pool.executable(&ledger).iter().map(|tx| (tx.fee, tx.nonce)).collect();
+        assert_eq!(selected, vec![(7, 4), (2, 0)]);
+    }
+}
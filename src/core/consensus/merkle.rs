@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::core::consensus::transaction::compute_transaction_hash;
+use crate::core::types::{Block, BlockHeader, Transaction};
+
+/// Merkle path from one transaction to `BlockHeader::merkle_root`.
+/// `siblings[0]` is next to the leaf; `None` marks a level where the node was
+/// the odd one out and was promoted by hashing it alone.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TransactionProof {
+    pub tx_hash: String,
+    pub index: u32,
+    pub siblings: Vec<Option<[u8; 32]>>,
+}
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Leaf hash of a transaction: `sha256(0x00 || tx_hash || signature)`. The
+/// transaction hash covers everything but the signature, so the leaf commits
+/// to both, and the prefix keeps a leaf from ever passing as an inner node.
+pub fn merkle_leaf(tx: &Transaction) -> [u8; 32] {
+    leaf_hash(&tx.hash, &tx.signature)
+}
+
+fn leaf_hash(tx_hash: &str, signature: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(tx_hash.as_bytes());
+    hasher.update(signature);
+    hasher.finalize().into()
+}
+
+/// Every level of the tree, leaves first and root last. Pairs are hashed as
+/// `sha256(0x01 || left || right)`; an odd node at the end of a level is
+/// never duplicated but promoted as `sha256(0x01 || node)`. Together with the
+/// `tx_count` in the header this fixes a single tree shape for every root.
+pub fn merkle_levels(transactions: &[Transaction]) -> Vec<Vec<[u8; 32]>> {
+    let mut levels = vec![transactions.iter().map(merkle_leaf).collect::<Vec<_>>()];
+
+    while levels.last().is_some_and(|level| level.len() > 1) {
+        let next = levels
+            .last()
+            .unwrap()
+            .chunks(2)
+            .map(|chunk| parent_hash(&chunk[0], chunk.get(1)))
+            .collect();
+        levels.push(next);
+    }
+
+    levels
+}
+
+/// Builds the inclusion proof for the transaction with `tx_hash` in `block`.
+pub fn prove_transaction(block: &Block, tx_hash: &str) -> Result<TransactionProof, String> {
+    let index = block
+        .transactions
+        .iter()
+        .position(|tx| tx.hash == tx_hash)
+        .ok_or_else(|| format!("Transaction {} is not in the block", tx_hash))?;
+
+    let levels = merkle_levels(&block.transactions);
+    let mut position = index;
+    let mut siblings = Vec::new();
+    for level in &levels[..levels.len() - 1] {
+        siblings.push(level.get(position ^ 1).copied());
+        position /= 2;
+    }
+
+    Ok(TransactionProof {
+        tx_hash: tx_hash.to_string(),
+        index: index as u32,
+        siblings,
+    })
+}
+
+/// Checks that `tx` is included in the block with `header`. Only the header is
+/// needed, so light clients and bridges can call this without the block body.
+/// The leaf is rebuilt from `proof.tx_hash`, which must be the hash of `tx`'s
+/// contents, not merely the hash it claims.
+pub fn verify_transaction_proof(header: &BlockHeader, tx: &Transaction, proof: &TransactionProof) -> bool {
+    if proof.index >= header.tx_count {
+        return false;
+    }
+    if compute_transaction_hash(tx).ok().as_deref() != Some(proof.tx_hash.as_str()) {
+        return false;
+    }
+
+    let mut hash = leaf_hash(&proof.tx_hash, &tx.signature);
+    let mut position = proof.index as usize;
+    let mut width = header.tx_count as usize;
+    let mut siblings = proof.siblings.iter();
+
+    while width > 1 {
+        let Some(sibling) = siblings.next() else {
+            return false;
+        };
+
+        // The shape is fixed by `tx_count`: only the last node of an odd-sized
+        // level may lack a sibling.
+        let is_odd_tail = !width.is_multiple_of(2) && position == width - 1;
+        hash = match (sibling, is_odd_tail) {
+            (None, true) => parent_hash(&hash, None),
+            (Some(sibling), false) if position.is_multiple_of(2) => parent_hash(&hash, Some(sibling)),
+            (Some(sibling), false) => parent_hash(sibling, Some(&hash)),
+            _ => return false,
+        };
+
+        position /= 2;
+        width = width.div_ceil(2);
+    }
+
+    siblings.next().is_none() && hash == header.merkle_root
+}
+
+fn parent_hash(left: &[u8; 32], right: Option<&[u8; 32]>) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    if let Some(right) = right {
+        hasher.update(right);
+    }
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use crate::core::api::v1::transaction::finalize_transaction;
+    use crate::core::consensus::block::{BLOCK_VERSION, compute_merkle_root};
+    use crate::core::test_utils::{address_of, signing_key};
+    use crate::core::types::{TransactionStatus, TransactionType};
+
+    fn transactions(count: u64) -> Vec<Transaction> {
+        let from = signing_key(1);
+        (0..count)
+            .map(|nonce| {
+                let mut tx = Transaction {
+                    hash: String::new(),
+                    r#type: TransactionType::Transfer,
+                    status: TransactionStatus::Pending,
+                    index: nonce as u32,
+                    nonce,
+                    timestamp: Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap(),
+                    from: address_of(&from),
+                    to: address_of(&signing_key(2)),
+                    amount: 10 + nonce,
+                    fee: 1,
+                    memo: 0,
+                    nrc_memo: 0,
+                    signature: vec![],
+                };
+                finalize_transaction(&mut tx, &from).unwrap();
+                tx
+            })
+            .collect()
+    }
+
+    fn block(transactions: Vec<Transaction>) -> Block {
+        let header = BlockHeader {
+            version: BLOCK_VERSION,
+            subnet_id: "subnet-test".to_string(),
+            height: 0,
+            parent_hash: [0u8; 32],
+            slot: 0,
+            merkle_root: compute_merkle_root(&transactions),
+            state_root: [0u8; 32],
+            ledger_root: [0u8; 32],
+            tx_count: transactions.len() as u32,
+            total_fees: 0,
+            evidence_hash: [0u8; 32],
+            unjail_hash: [0u8; 32],
+            timestamp: 0,
+            validator: String::new(),
+            signature: vec![],
+        };
+        Block { header, transactions, evidence: vec![], unjails: vec![] }
+    }
+
+    #[test]
+    fn every_transaction_proves_with_odd_leaf_counts() {
+        for count in [1, 3, 5, 7] {
+            let block = block(transactions(count));
+            for tx in &block.transactions {
+                let proof = prove_transaction(&block, &tx.hash).unwrap();
+                assert!(verify_transaction_proof(&block.header, tx, &proof), "{} of {} failed", tx.index, count);
+            }
+        }
+    }
+
+    #[test]
+    fn odd_tail_is_promoted_not_duplicated() {
+        let block = block(transactions(3));
+        let proof = prove_transaction(&block, &block.transactions[2].hash).unwrap();
+        assert_eq!(proof.siblings[0], None);
+
+        // Duplicating the last leaf would give a four-leaf tree with the same
+        // root under Bitcoin-style hashing; here the roots differ.
+        let mut padded = block.transactions.clone();
+        padded.push(block.transactions[2].clone());
+        assert_ne!(compute_merkle_root(&padded), block.header.merkle_root);
+    }
+
+    #[test]
+    fn proof_fails_for_other_position_or_transaction() {
+        let block = block(transactions(5));
+        let tx = &block.transactions[4];
+        let proof = prove_transaction(&block, &tx.hash).unwrap();
+
+        let mut moved = proof.clone();
+        moved.index = 3;
+        assert!(!verify_transaction_proof(&block.header, tx, &moved));
+
+        let mut shape = block.header.clone();
+        shape.tx_count = 6;
+        assert!(!verify_transaction_proof(&shape, tx, &proof));
+
+        assert!(!verify_transaction_proof(&block.header, &block.transactions[0], &proof));
+    }
+
+    #[test]
+    fn proof_fails_for_altered_contents_under_the_same_hash() {
+        let block = block(transactions(3));
+        let proof = prove_transaction(&block, &block.transactions[1].hash).unwrap();
+
+        let mut altered = block.transactions[1].clone();
+        altered.amount += 1;
+        assert!(!verify_transaction_proof(&block.header, &altered, &proof));
+    }
+}
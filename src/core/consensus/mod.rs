@@ -4,11 +4,36 @@ pub mod block;
 pub mod neuron;
 pub mod utils;
 pub mod model;
+#[allow(clippy::module_inception)]
 pub mod consensus;
 pub mod math;
+pub mod finality;
+pub mod execution;
+pub mod tree;
+pub mod state;
+pub mod ledger_tree;
+pub mod merkle;
+pub mod mempool;
+pub mod template;
+pub mod evidence;
+pub mod epoch;
+pub mod liveness;
+pub mod slot;
 
-pub use validator::{ValidatorInfo, select_next_validator, slash};
-pub use transaction::{add_transaction, compute_transaction_hash};
-pub use block::{produce_block, validate_block, compute_merkle_root, hash_block, serialize_header_for_signing};
+pub use validator::{ValidatorInfo, select_leader, select_next_validator, stake_weights};
+pub use transaction::{add_transaction, compute_transaction_hash, pending_transactions, verify_transaction};
+pub use mempool::{Mempool, MempoolConfig, PooledTransaction};
+pub use template::{BlockTemplate, build_block_template};
+pub use epoch::{EpochValidatorSet, epoch_of, get_validator_set, init_genesis_validator_set, validator_set_at, validator_set_of_block};
+pub use slot::{current_slot, next_slot, slot_at, slot_start_ms};
+pub use liveness::{add_unjail, get_liveness, sign_unjail, verify_unjail};
+pub use evidence::{add_evidence, detect_equivocation, evidence_id, verify_evidence};
+pub use block::{BLOCK_VERSION, produce_block, verify_block_stateless, validate_block, import_block, compute_merkle_root, hash_block, hash_header, serialize_header_for_signing};
 pub use neuron::delegate_stake;
+pub use execution::{BlockOutcome, BlockUndo, TransactionResult, apply_block, get_transaction_result, revert_block};
+pub use tree::{BlockNode, BlockTree, insert_block};
+pub use state::{compute_ledger_root, compute_state_root};
+pub use ledger_tree::{AccountProof, FinalizedAccountProof, ledger_root, prove_account, prove_finalized_account, verify_account_proof};
+pub use merkle::{TransactionProof, merkle_leaf, prove_transaction, verify_transaction_proof};
+pub use finality::{BlockVote, FinalityCertificate, add_vote, finalized_height, get_certificate, has_voted, import_certificate, sign_block_vote, try_finalize, verify_certificate};
 pub use utils::crypto_hash;
@@ -1,37 +1,135 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::Arc;
 use ed25519_dalek::VerifyingKey;
 use rocksdb::DB;
 use serde::{Deserialize, Serialize};
 use crate::core::consensus::ValidatorInfo;
-use crate::core::types::{Block, DbWrapper, MutexWrapper, Neuron, Transaction};
+use crate::core::consensus::block::hash_block;
+use crate::core::consensus::execution::BlockUndo;
+use crate::core::consensus::epoch::EpochValidatorSet;
+use crate::core::consensus::finality::{BlockVote, FinalityCertificate};
+use crate::core::consensus::ledger_tree::LedgerTree;
+use crate::core::consensus::mempool::{Mempool, MempoolConfig, PooledTransaction};
+use crate::core::consensus::tree::{BlockNode, BlockTree};
+use crate::core::types::{decode_record, Block, DbWrapper, Evidence, MutexWrapper, Neuron, Unjail};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Account {
     pub address: String,
     pub public_key: VerifyingKey,
     pub balance: u64,
+    /// Nonce the next transaction from this account must carry.
+    pub nonce: u64,
+}
+
+/// Account record as persisted before `nonce` was added.
+#[derive(Deserialize)]
+struct LegacyAccount {
+    address: String,
+    public_key: VerifyingKey,
+    balance: u64,
+}
+
+impl From<LegacyAccount> for Account {
+    fn from(legacy: LegacyAccount) -> Self {
+        Account {
+            address: legacy.address,
+            public_key: legacy.public_key,
+            balance: legacy.balance,
+            nonce: 0,
+        }
+    }
+}
+
+/// Consensus rules read from the `[consensus]` config section. Every node in a
+/// subnet must use the same values or they will disagree on block contents.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ConsensusConfig {
+    /// Smallest fee a transaction may carry.
+    pub min_fee: u64,
+    /// Share of every fee that is burned; the rest goes to the block producer.
+    pub fee_burn_percent: u64,
+    /// Most transactions a block may carry.
+    pub max_block_transactions: usize,
+    /// Largest serialized size of a block's transactions, in bytes.
+    pub max_block_bytes: usize,
+    /// Blocks per epoch; the validator set only changes between epochs.
+    pub epoch_length: u64,
+    /// Stake a validator's own neuron needs to join an epoch's set.
+    pub min_self_stake: u64,
+    /// Largest validator set, keeping those with the most backing stake.
+    pub max_validators: usize,
+    /// Number of most recent slots over which missed slots are counted.
+    pub liveness_window: u64,
+    /// Missed slots within the window that get a validator jailed.
+    pub max_missed_slots: u64,
+    /// Blocks a jailed validator must wait before it can be unjailed.
+    pub unjail_cooldown: u64,
+    /// Start of slot 0 in unix milliseconds; 0 uses the timestamp of block 0.
+    pub genesis_time: u64,
+    /// Length of a slot in milliseconds. Each slot has one leader and holds at
+    /// most one block.
+    pub slot_duration_ms: u64,
+    /// Balances every node seeds before block 0, keyed by account address
+    /// (the hex public key). Only these and what blocks do to them count
+    /// towards `ledger_root`.
+    pub genesis_allocations: BTreeMap<String, u64>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ConsensusEngine {
+    pub subnet_id: String,
+    pub config: ConsensusConfig,
     pub validators: Arc<MutexWrapper<Vec<ValidatorInfo>>>,
     pub neurons: Arc<MutexWrapper<HashMap<u64, Neuron>>>,
-    pub mempool: Arc<MutexWrapper<Vec<Transaction>>>,
+    pub mempool: Arc<MutexWrapper<Mempool>>,
     pub chain: Arc<MutexWrapper<Vec<Block>>>,
+    pub tree: Arc<MutexWrapper<BlockTree>>,
+    pub undo: Arc<MutexWrapper<HashMap<[u8; 32], BlockUndo>>>,
     pub ledger: Arc<MutexWrapper<HashMap<String, Account>>>,
+    /// Account tree over `ledger`, updated whenever a block changes an account.
+    pub ledger_tree: Arc<MutexWrapper<LedgerTree>>,
+    pub votes: Arc<MutexWrapper<HashMap<[u8; 32], Vec<BlockVote>>>>,
+    pub certificates: Arc<MutexWrapper<HashMap<u64, FinalityCertificate>>>,
+    pub finalized_height: Arc<MutexWrapper<Option<u64>>>,
+    /// The last block vote this node signed.
+    pub last_vote: Arc<MutexWrapper<Option<BlockVote>>>,
+    /// Validator set snapshot of each epoch, keyed by the hash of the block
+    /// that closed the previous epoch (all zeroes for epoch 0), so every
+    /// branch keeps its own snapshots.
+    pub epochs: Arc<MutexWrapper<HashMap<[u8; 32], EpochValidatorSet>>>,
+    /// Verified double-sign evidence waiting to be included in a block.
+    pub evidence: Arc<MutexWrapper<Vec<Evidence>>>,
+    /// Verified unjail requests waiting to be included in a block.
+    pub unjails: Arc<MutexWrapper<Vec<Unjail>>>,
+    /// Held by block import and production from validation until the head is
+    /// updated, so no other block can change the state in between.
+    pub import_lock: Arc<MutexWrapper<()>>,
     #[serde(skip)]
     pub db: DbWrapper,
 }
 
 impl ConsensusEngine {
-    pub fn new(validators: Arc<MutexWrapper<Vec<ValidatorInfo>>>, neurons: Arc<MutexWrapper<HashMap<u64, Neuron>>>, db: Arc<DB>) -> Self {
+    pub fn new(subnet_id: String, config: ConsensusConfig, mempool_config: MempoolConfig, validators: Arc<MutexWrapper<Vec<ValidatorInfo>>>, neurons: Arc<MutexWrapper<HashMap<u64, Neuron>>>, db: Arc<DB>) -> Self {
         let mut engine = Self {
+            subnet_id,
+            config,
             validators,
             neurons,
-            mempool: Arc::new(MutexWrapper::new(Vec::new())),
+            mempool: Arc::new(MutexWrapper::new(Mempool::new(mempool_config))),
             chain: Arc::new(MutexWrapper::new(Vec::new())),
+            tree: Arc::new(MutexWrapper::new(BlockTree::default())),
+            undo: Arc::new(MutexWrapper::new(HashMap::new())),
             ledger: Arc::new(MutexWrapper::new(HashMap::new())),
+            ledger_tree: Arc::new(MutexWrapper::new(LedgerTree::default())),
+            votes: Arc::new(MutexWrapper::new(HashMap::new())),
+            certificates: Arc::new(MutexWrapper::new(HashMap::new())),
+            finalized_height: Arc::new(MutexWrapper::new(None)),
+            last_vote: Arc::new(MutexWrapper::new(None)),
+            epochs: Arc::new(MutexWrapper::new(HashMap::new())),
+            evidence: Arc::new(MutexWrapper::new(Vec::new())),
+            unjails: Arc::new(MutexWrapper::new(Vec::new())),
+            import_lock: Arc::new(MutexWrapper::new(())),
             db: DbWrapper(db),
         };
 
@@ -62,12 +160,23 @@ impl ConsensusEngine {
 
         {
             let mempool = self.mempool.lock();
-            for (i, tx) in mempool.iter().enumerate() {
-                let serialized = bincode::serialize(tx).unwrap();
+            let mut count = 0;
+            for (i, pooled) in mempool.transactions().enumerate() {
+                let serialized = bincode::serialize(pooled).unwrap();
                 let key = format!("mempool_{}", i);
                 self.db.put(key.as_bytes(), serialized).unwrap();
+                count = i + 1;
             }
             drop(mempool);
+
+            // Clear entries left over from when the pool was larger.
+            for i in count.. {
+                let key = format!("mempool_{}", i);
+                if !matches!(self.db.get(&key), Ok(Some(_))) {
+                    break;
+                }
+                self.db.delete(key.as_bytes()).unwrap();
+            }
         }
 
         {
@@ -76,9 +185,25 @@ impl ConsensusEngine {
             self.db.put(b"validators", serialized).unwrap();
             drop(validators);
         }
+
+        {
+            let tree = self.ledger_tree.lock();
+            let serialized = bincode::serialize(tree.local_accounts()).unwrap();
+            self.db.put(b"local_accounts", serialized).unwrap();
+        }
     }
 
 
+    pub fn persist_neurons(&self) {
+        let neurons = self.neurons.lock();
+        for (id, neuron) in neurons.iter() {
+            let serialized = bincode::serialize(neuron).unwrap();
+            let mut key: Vec<u8> = b"neuron_".to_vec();
+            key.extend_from_slice(&id.to_le_bytes());
+            self.db.put(key, serialized).unwrap();
+        }
+    }
+
     fn load_state(&mut self) {
         {
             let mut ledger_lock = self.ledger.lock();
@@ -86,11 +211,21 @@ impl ConsensusEngine {
             let iter = self.db.0.iterator(rocksdb::IteratorMode::Start);
             for item in iter {
                 let (key, value) = item.unwrap();
-                let key_str = String::from_utf8(key.to_vec()).unwrap();
-                if let Ok(account) = bincode::deserialize::<Account>(&value) {
-                    ledger_lock.insert(key_str, account);
+                if !key.starts_with(b"ledger_") {
+                    continue;
+                }
+                match decode_record::<Account, LegacyAccount>(&self.db, &key, &value) {
+                    Ok(account) => {
+                        ledger_lock.insert(account.address.clone(), account);
+                    }
+                    Err(err) => eprintln!("❌ Skipping account record: {}", err),
                 }
             }
+            let local: BTreeSet<String> = match self.db.get("local_accounts") {
+                Ok(Some(value)) => bincode::deserialize(&value).unwrap_or_default(),
+                _ => BTreeSet::new(),
+            };
+            *self.ledger_tree.lock() = LedgerTree::from_ledger(&ledger_lock, local);
             drop(ledger_lock)
         }
 
@@ -107,18 +242,96 @@ impl ConsensusEngine {
                     break;
                 }
             }
+
+            let mut tree_lock = self.tree.lock();
+            tree_lock.nodes = chain_lock
+                .iter()
+                .enumerate()
+                .map(|(height, block)| (hash_block(block), BlockNode { block: block.clone(), height: height as u64 }))
+                .collect();
+
+            let mut undo_lock = self.undo.lock();
+            undo_lock.clear();
+            for block in chain_lock.iter() {
+                let block_hash = hash_block(block);
+                let key = format!("undo_{}", hex::encode(block_hash));
+                if let Ok(Some(value)) = self.db.get(&key)
+                    && let Ok(undo) = bincode::deserialize::<BlockUndo>(&value)
+                {
+                    undo_lock.insert(block_hash, undo);
+                }
+            }
             drop(chain_lock)
         }
 
+        {
+            let mut certificates_lock = self.certificates.lock();
+            certificates_lock.clear();
+            let iter = self.db.0.iterator(rocksdb::IteratorMode::Start);
+            for item in iter {
+                let (key, value) = item.unwrap();
+                if key.starts_with(b"certificate_")
+                    && let Ok(certificate) = bincode::deserialize::<FinalityCertificate>(&value)
+                {
+                    certificates_lock.insert(certificate.height, certificate);
+                }
+            }
+
+            let mut finalized_lock = self.finalized_height.lock();
+            *finalized_lock = match self.db.get("finalized_height") {
+                Ok(Some(value)) => value.as_slice().try_into().ok().map(u64::from_be_bytes),
+                _ => None,
+            };
+
+            let mut last_vote_lock = self.last_vote.lock();
+            *last_vote_lock = match self.db.get("last_vote") {
+                Ok(Some(value)) => bincode::deserialize(&value).ok(),
+                _ => None,
+            };
+        }
+
+        {
+            let chain_lock = self.chain.lock();
+            let mut epochs_lock = self.epochs.lock();
+            epochs_lock.clear();
+            let iter = self.db.0.iterator(rocksdb::IteratorMode::Start);
+            for item in iter {
+                let (key, value) = item.unwrap();
+                let Ok(set) = bincode::deserialize::<EpochValidatorSet>(&value) else {
+                    continue;
+                };
+                if let Some(anchor) = key.strip_prefix(b"validator_set_") {
+                    if let Some(anchor) = hex::decode(anchor).ok().and_then(|anchor| <[u8; 32]>::try_from(anchor).ok()) {
+                        epochs_lock.insert(anchor, set);
+                    }
+                } else if key.starts_with(b"epoch_") {
+                    // Snapshots used to be keyed by epoch number alone; they
+                    // belong to the canonical chain they were taken on.
+                    let start = set.epoch * self.config.epoch_length.max(1);
+                    let anchor = match start.checked_sub(1) {
+                        None => Some([0u8; 32]),
+                        Some(boundary) => chain_lock.get(boundary as usize).map(hash_block),
+                    };
+                    if let Some(anchor) = anchor {
+                        let new_key = format!("validator_set_{}", hex::encode(anchor));
+                        self.db.put(new_key.as_bytes(), &value).unwrap();
+                        epochs_lock.insert(anchor, set);
+                    }
+                    self.db.delete(&key).unwrap();
+                }
+            }
+        }
+
         {
             let mut mempool_lock = self.mempool.lock();
             mempool_lock.clear();
             for i in 0.. {
                 let key = format!("mempool_{}", i);
                 if let Ok(Some(value)) = self.db.get(&key) {
-                    if let Ok(tx) = bincode::deserialize::<Transaction>(&value) {
+                    if let Ok(pooled) = bincode::deserialize::<PooledTransaction>(&value)
+                        && mempool_lock.insert_pooled(pooled).is_ok()
+                    {
                         println!("Loaded transaction {}", i);
-                        mempool_lock.push(tx);
                     }
                 } else {
                     break;
@@ -129,26 +342,28 @@ impl ConsensusEngine {
 
         {
             let mut validators_lock = self.validators.lock();
-            if let Ok(Some(value)) = self.db.get("validators") {
-                if let Ok(validators) = bincode::deserialize::<Vec<ValidatorInfo>>(&value) {
-                    *validators_lock = validators;
-                }
+            if let Ok(Some(value)) = self.db.get("validators")
+                && let Ok(validators) = bincode::deserialize::<Vec<ValidatorInfo>>(&value)
+            {
+                *validators_lock = validators;
             }
             drop(validators_lock)
         }
     }
 
     pub fn init_ledger(&mut self, address: String, public_key: VerifyingKey, balance: u64) -> Option<Account> {
-        let account = Account {
-            address: address.clone(),
-            public_key,
-            balance,
-        };
-
         {
             let mut ledger = self.ledger.lock();
+            let nonce = ledger.get(&address).map(|account| account.nonce).unwrap_or(0);
+            let account = Account {
+                address: address.clone(),
+                public_key,
+                balance,
+                nonce,
+            };
             ledger.insert(address.clone(), account);
         }
+        self.mark_local_accounts(std::slice::from_ref(&address));
 
         self.persist_state();
 
@@ -156,15 +371,57 @@ impl ConsensusEngine {
         ledger.get(&address).cloned()
     }
 
+    /// Seeds the `genesis_allocations` of the config into the ledger and the
+    /// account tree. Only done before block 0, so every node starts from the
+    /// same accounts.
+    pub fn init_genesis_ledger(&mut self) -> Result<(), String> {
+        if !self.chain.lock().is_empty() {
+            return Ok(());
+        }
+
+        {
+            let mut ledger = self.ledger.lock();
+            let mut tree = self.ledger_tree.lock();
+            for (address, balance) in &self.config.genesis_allocations {
+                let key_bytes: [u8; 32] = hex::decode(address)
+                    .map_err(|e| format!("Invalid genesis address {}: {}", address, e))?
+                    .try_into()
+                    .map_err(|_| format!("Invalid genesis address {}: expected 32 bytes", address))?;
+                let public_key = VerifyingKey::from_bytes(&key_bytes)
+                    .map_err(|e| format!("Invalid genesis address {}: {}", address, e))?;
+
+                let account = Account {
+                    address: address.clone(),
+                    public_key,
+                    balance: *balance,
+                    nonce: 0,
+                };
+                tree.update(address, Some(&account));
+                ledger.insert(address.clone(), account);
+            }
+        }
+
+        self.persist_state();
+        Ok(())
+    }
+
+    /// Takes `addresses` out of the account tree for good. Anything that
+    /// changes an account outside block execution calls this, since other
+    /// nodes never see the change and could not agree on the ledger root.
+    pub fn mark_local_accounts(&self, addresses: &[String]) {
+        let mut tree = self.ledger_tree.lock();
+        for address in addresses {
+            tree.mark_local(address);
+        }
+    }
+
     pub fn get_ledger(&mut self, address: String) -> Option<Account> {
         let ledger = self.ledger.lock();
-        let result = ledger.get(&address).cloned();
-        result
+        ledger.get(&address).cloned()
     }
 
     pub fn get_balance(&mut self, address: &str) -> u64 {
         let ledger = self.ledger.lock();
-        let balance = ledger.get(address).map(|account| account.balance).unwrap_or(0);
-        balance
+        ledger.get(address).map(|account| account.balance).unwrap_or(0)
     }
 }
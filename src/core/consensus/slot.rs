@@ -0,0 +1,87 @@
+use chrono::Utc;
+use crate::core::consensus::model::ConsensusEngine;
+
+/// How far ahead of the local clock a block's slot may start before the block
+/// is rejected as coming from the future.
+pub const MAX_CLOCK_DRIFT_MS: u64 = 500;
+
+/// Start of slot 0 in unix milliseconds: `genesis_time` from the config, or
+/// the timestamp of block 0 when none is configured. `None` until the genesis
+/// block exists in that case.
+pub fn genesis_time_ms(consensus_engine: &ConsensusEngine) -> Option<u64> {
+    if consensus_engine.config.genesis_time > 0 {
+        return Some(consensus_engine.config.genesis_time);
+    }
+    let chain_lock = consensus_engine.chain.lock();
+    chain_lock.first().map(|genesis| genesis.header.timestamp * 1000)
+}
+
+/// Slot in progress at `time_ms`. Everything before genesis is slot 0.
+pub fn slot_at(consensus_engine: &ConsensusEngine, time_ms: u64) -> u64 {
+    match genesis_time_ms(consensus_engine) {
+        Some(genesis) => time_ms.saturating_sub(genesis) / consensus_engine.config.slot_duration_ms.max(1),
+        None => 0,
+    }
+}
+
+/// Unix time in milliseconds at which `slot` begins.
+pub fn slot_start_ms(consensus_engine: &ConsensusEngine, slot: u64) -> u64 {
+    let genesis = genesis_time_ms(consensus_engine).unwrap_or(0);
+    genesis + slot * consensus_engine.config.slot_duration_ms.max(1)
+}
+
+pub fn now_ms() -> u64 {
+    Utc::now().timestamp_millis() as u64
+}
+
+pub fn current_slot(consensus_engine: &ConsensusEngine) -> u64 {
+    slot_at(consensus_engine, now_ms())
+}
+
+/// Slot the next block on top of the tip would fill: the current slot, or the
+/// one after the tip if the tip already filled it.
+pub fn next_slot(consensus_engine: &ConsensusEngine) -> u64 {
+    let tip_slot = consensus_engine.chain.lock().last().map(|blk| blk.header.slot);
+    let current = current_slot(consensus_engine);
+    match tip_slot {
+        Some(slot) => current.max(slot + 1),
+        None => current,
+    }
+}
+
+/// Whether the slot the next block would fill has started, so the leader may
+/// produce it now.
+pub fn is_slot_open(consensus_engine: &ConsensusEngine) -> bool {
+    next_slot(consensus_engine) <= current_slot(consensus_engine)
+}
+
+/// Milliseconds until the slot after the current one begins, or one slot
+/// duration while the genesis time is not known yet.
+pub fn time_until_next_slot(consensus_engine: &ConsensusEngine) -> u64 {
+    if genesis_time_ms(consensus_engine).is_none() {
+        return consensus_engine.config.slot_duration_ms;
+    }
+    let next_start = slot_start_ms(consensus_engine, current_slot(consensus_engine) + 1);
+    next_start.saturating_sub(now_ms())
+}
+
+/// Checks a block's slot against its parent's slot and the local clock: slots
+/// must strictly increase along a branch and may not lie in the future.
+pub fn validate_block_slot(
+    consensus_engine: &ConsensusEngine,
+    slot: u64,
+    parent_slot: Option<u64>,
+) -> Result<(), String> {
+    if let Some(parent_slot) = parent_slot
+        && slot <= parent_slot
+    {
+        return Err(format!("Slot {} is already filled, parent is at slot {}", slot, parent_slot));
+    }
+
+    let allowed = slot_at(consensus_engine, now_ms() + MAX_CLOCK_DRIFT_MS);
+    if slot > allowed {
+        return Err(format!("Block is for future slot {}, current slot is {}", slot, current_slot(consensus_engine)));
+    }
+
+    Ok(())
+}
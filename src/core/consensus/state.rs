@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use crate::core::consensus::model::{Account, ConsensusEngine};
+use crate::core::consensus::validator::ValidatorInfo;
+use crate::core::types::{Address, Neuron};
+
+/// State root of the canonical tip, or zero before genesis.
+pub fn compute_state_root(consensus_engine: &ConsensusEngine) -> [u8; 32] {
+    let chain_lock = consensus_engine.chain.lock();
+    chain_lock.last().map(|blk| blk.header.state_root).unwrap_or([0u8; 32])
+}
+
+pub fn compute_ledger_root(consensus_engine: &ConsensusEngine) -> [u8; 32] {
+    consensus_engine.ledger_tree.lock().root()
+}
+
+/// Everything a block changes, as committed to by its state root.
+#[derive(Serialize)]
+struct StateChanges<'a> {
+    /// Balance and nonce change of every touched account, in order.
+    ledger: Vec<(&'a String, i128, u64)>,
+    /// Stake change of every neuron whose stake moved, by neuron id.
+    stakes: Vec<(u64, i128)>,
+    /// New `active` and `jailed_at` of every validator whose jail state
+    /// changed, by address.
+    jails: Vec<(&'a Address, bool, Option<u64>)>,
+}
+
+/// Commitment to the state block execution changes:
+/// `sha256(parent_root || changes)`, where `changes` covers the ledger
+/// accounts in `touched`, neuron stakes and validator jail state. Balances
+/// seeded by `init_ledger`, neurons and governance are set up outside blocks,
+/// so only what a block does to them is committed, never their absolute values.
+pub fn state_root_of(
+    parent_root: &[u8; 32],
+    touched: &[(String, Option<Account>)],
+    ledger_after: &HashMap<String, Account>,
+    neurons_before: &HashMap<u64, Neuron>,
+    neurons_after: &HashMap<u64, Neuron>,
+    validators_before: &[ValidatorInfo],
+    validators_after: &[ValidatorInfo],
+) -> [u8; 32] {
+    let ledger = touched
+        .iter()
+        .map(|(address, before)| {
+            let after = ledger_after.get(address);
+            let balance = |account: Option<&Account>| account.map(|account| account.balance as i128).unwrap_or(0);
+            let nonce = |account: Option<&Account>| account.map(|account| account.nonce).unwrap_or(0);
+            (
+                address,
+                balance(after) - balance(before.as_ref()),
+                nonce(after).wrapping_sub(nonce(before.as_ref())),
+            )
+        })
+        .collect();
+
+    let stake = |neurons: &HashMap<u64, Neuron>, id: &u64| neurons.get(id).map(|neuron| neuron.staked_amount as i128).unwrap_or(0);
+    let mut ids: Vec<u64> = neurons_before.keys().chain(neurons_after.keys()).copied().collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let stakes = ids
+        .into_iter()
+        .map(|id| (id, stake(neurons_after, &id) - stake(neurons_before, &id)))
+        .filter(|(_, delta)| *delta != 0)
+        .collect();
+
+    let mut jails: Vec<(&Address, bool, Option<u64>)> = validators_after
+        .iter()
+        .filter(|after| {
+            validators_before
+                .iter()
+                .find(|before| before.address == after.address)
+                .is_none_or(|before| before.active != after.active || before.jailed_at != after.jailed_at)
+        })
+        .map(|validator| (&validator.address, validator.active, validator.jailed_at))
+        .collect();
+    jails.sort();
+
+    let changes = StateChanges { ledger, stakes, jails };
+    let mut hasher = Sha256::new();
+    hasher.update(parent_root);
+    hasher.update(bincode::serialize(&changes).unwrap());
+    hasher.finalize().into()
+}
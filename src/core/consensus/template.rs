@@ -0,0 +1,62 @@
+use std::collections::HashSet;
+use crate::core::consensus::execution::execute_transaction;
+use crate::core::consensus::model::ConsensusEngine;
+use crate::core::consensus::transaction::executable_transactions;
+use crate::core::types::Transaction;
+
+/// Transactions chosen for the next block, with what they add up to.
+#[derive(Clone, Debug, Default)]
+pub struct BlockTemplate {
+    pub transactions: Vec<Transaction>,
+    pub total_fees: u64,
+    pub size_bytes: usize,
+}
+
+/// Size a block body counts against `max_block_bytes`: the serialized size of
+/// each transaction.
+pub fn transactions_size(transactions: &[Transaction]) -> usize {
+    transactions
+        .iter()
+        .map(|tx| bincode::serialized_size(tx).unwrap_or(u64::MAX) as usize)
+        .fold(0, usize::saturating_add)
+}
+
+/// Picks the most valuable transactions that would execute successfully,
+/// highest fee first and in nonce order per sender, until the block count or
+/// size limit is reached. Once a sender's transaction is skipped, the rest of
+/// that sender's queue is skipped too so no nonce gap enters the block.
+pub fn build_block_template(consensus_engine: &ConsensusEngine) -> BlockTemplate {
+    let config = &consensus_engine.config;
+    let candidates = executable_transactions(consensus_engine);
+    let mut ledger = consensus_engine.ledger.lock().clone();
+
+    let mut template = BlockTemplate::default();
+    let mut blocked_senders: HashSet<String> = HashSet::new();
+
+    for tx in candidates {
+        if template.transactions.len() >= config.max_block_transactions {
+            break;
+        }
+        if blocked_senders.contains(&tx.from) {
+            continue;
+        }
+
+        let size = transactions_size(std::slice::from_ref(&tx));
+        if template.size_bytes + size > config.max_block_bytes {
+            blocked_senders.insert(tx.from.clone());
+            continue;
+        }
+
+        // A failed transaction leaves the ledger untouched.
+        if execute_transaction(config, &mut ledger, &tx).is_err() {
+            blocked_senders.insert(tx.from.clone());
+            continue;
+        }
+
+        template.size_bytes += size;
+        template.total_fees += tx.fee;
+        template.transactions.push(tx);
+    }
+
+    template
+}
@@ -1,107 +1,154 @@
+use chrono::Utc;
 use crate::core::types::Transaction;
 use crate::core::crypto::{verify_data};
 use ed25519_dalek::VerifyingKey;
 use bincode;
 use hex;
 use crate::core::consensus::{crypto_hash};
-use crate::core::consensus::model::{Account, ConsensusEngine};
+use crate::core::consensus::model::ConsensusEngine;
+
+/// How far past an account's nonce a transaction may be queued while it waits
+/// for the gap to be filled.
+const MAX_FUTURE_NONCES: u64 = 64;
+
+/// Checks that `tx.hash` matches its contents and that it is signed by the
+/// sender. These checks do not depend on local state, so a transaction that
+/// fails them is invalid everywhere.
+pub fn verify_transaction(tx: &Transaction) -> Result<(), String> {
+    let expected_hash = compute_transaction_hash(tx)?;
+
+    if tx.hash != expected_hash {
+        return Err(format!(
+            "Invalid transaction hash: Expected {}, got {}",
+            expected_hash, tx.hash
+        ));
+    }
+
+    let sender_pubkey_bytes = hex::decode(&tx.from)
+        .map_err(|_| format!("Invalid sender address format: {}", &tx.from))?;
+
+    if sender_pubkey_bytes.len() != 32 {
+        return Err(format!(
+            "Invalid public key length: Expected 32 bytes, got {}",
+            sender_pubkey_bytes.len()
+        ));
+    }
+
+    let sender_pubkey = VerifyingKey::from_bytes(&sender_pubkey_bytes.try_into().unwrap())
+        .map_err(|_| "Invalid sender public key: Failed to create VerifyingKey")?;
+
+    let signature_copy = tx.signature.clone();
+    let mut tx_clone = tx.clone();
+    tx_clone.signature.clear();
+    tx_clone.hash.clear();
 
+    let serialized_tx = bincode::serialize(&tx_clone)
+        .map_err(|e| format!("Verification Serialization Error: {}", e))?;
+
+    if signature_copy.is_empty() {
+        return Err("Invalid transaction signature: Signature is missing.".to_string());
+    }
+
+    if !verify_data(&sender_pubkey, &serialized_tx, &signature_copy) {
+        return Err("Invalid transaction signature: Signature does not match.".to_string());
+    }
+
+    Ok(())
+}
+
+/// Verifies a transaction and queues it in the mempool. Balances are not
+/// touched until the transaction is executed as part of a block. Nonces above
+/// the account nonce are accepted and wait in the mempool until executable.
 pub fn add_transaction(
     consensus_engine: &mut ConsensusEngine,
     tx: Transaction,
 ) -> Result<(), String> {
     {
-        let expected_hash = compute_transaction_hash(&tx)?;
+        verify_transaction(&tx)?;
 
-        if tx.hash != expected_hash {
-            return Err(format!(
-                "Invalid transaction hash: Expected {}, got {}",
-                expected_hash, tx.hash
-            ));
+        if consensus_engine.mempool.lock().contains(&tx.hash) {
+            return Err(format!("Transaction {} already in mempool", tx.hash));
         }
 
-        let sender_pubkey_bytes = hex::decode(&tx.from)
-            .map_err(|_| format!("Invalid sender address format: {}", &tx.from))?;
-
-        if sender_pubkey_bytes.len() != 32 {
+        if tx.fee < consensus_engine.config.min_fee {
             return Err(format!(
-                "Invalid public key length: Expected 32 bytes, got {}",
-                sender_pubkey_bytes.len()
+                "Transaction fee {} is below the minimum fee {}",
+                tx.fee, consensus_engine.config.min_fee
             ));
         }
 
-        let sender_pubkey = VerifyingKey::from_bytes(&sender_pubkey_bytes.try_into().unwrap())
-            .map_err(|_| "Invalid sender public key: Failed to create VerifyingKey")?;
-
-        let signature_copy = tx.signature.clone();
-        let mut tx_clone = tx.clone();
-        tx_clone.signature.clear();
-        tx_clone.hash.clear();
+        // Admission only checks that the sender can cover this transfer on top
+        // of what it already has queued; the ledger changes when a block
+        // containing the transaction is executed.
+        let pending_outgoing = consensus_engine.mempool.lock().pending_outgoing(&tx.from);
 
-        let serialized_tx = bincode::serialize(&tx_clone)
-            .map_err(|e| format!("Verification Serialization Error: {}", e))?;
+        let ledger = consensus_engine.ledger.lock();
+        let sender_balance = ledger.get(&tx.from).map(|account| account.balance).unwrap_or(0);
+        let account_nonce = ledger.get(&tx.from).map(|account| account.nonce).unwrap_or(0);
 
-        if signature_copy.is_empty() {
-            return Err("Invalid transaction signature: Signature is missing.".to_string());
+        if tx.nonce < account_nonce {
+            return Err(format!("Nonce {} already used: account nonce is {}", tx.nonce, account_nonce));
         }
-
-        if !verify_data(&sender_pubkey, &serialized_tx, &signature_copy) {
-            return Err("Invalid transaction signature: Signature does not match.".to_string());
+        if tx.nonce >= account_nonce + MAX_FUTURE_NONCES {
+            return Err(format!(
+                "Nonce {} is too far ahead of account nonce {}",
+                tx.nonce, account_nonce
+            ));
         }
-
-        let mut ledger = consensus_engine.ledger.lock();
-
-        let sender_account = match ledger.get(&tx.from) {
-            Some(account) => account.clone(),
-            None => {
-                let new_account = Account {
-                    address: tx.from.clone(),
-                    public_key: sender_pubkey,
-                    balance: 0,
-                };
-                ledger.insert(tx.from.clone(), new_account.clone());
-                new_account
-            }
-        };
-
-        if sender_account.balance < tx.amount {
+        let cost = tx.amount.saturating_add(tx.fee);
+        if sender_balance < pending_outgoing.saturating_add(cost) {
             return Err(format!(
-                "Insufficient funds: Sender balance is {} but transaction amount is {}",
-                sender_account.balance, tx.amount
+                "Insufficient funds: Sender balance is {} with {} pending but transaction costs {}",
+                sender_balance, pending_outgoing, cost
             ));
         }
 
-        let receiver_account = match ledger.get(&tx.to) {
-            Some(account) => account.clone(),
-            None => return Err("Invalid transaction receiver account.".to_string()),
-        };
-
-        let updated_sender = Account {
-            balance: sender_account.balance - tx.amount,
-            ..sender_account.clone()
-        };
-        ledger.insert(tx.from.clone(), updated_sender);
-
-        let updated_receiver = Account {
-            balance: receiver_account.balance + tx.amount,
-            ..receiver_account.clone()
-        };
-        ledger.insert(tx.to.clone(), updated_receiver);
+        if !ledger.contains_key(&tx.to) {
+            return Err("Invalid transaction receiver account.".to_string());
+        }
+        drop(ledger);
 
         let mut mempool_lock = consensus_engine.mempool.lock();
-        mempool_lock.push(tx);
+        let now = Utc::now().timestamp();
+        mempool_lock.expire(now);
+        mempool_lock.insert(tx, now)?;
     }
 
     consensus_engine.persist_state();
     Ok(())
 }
 
+/// Nonce to give the next transaction built for `address`, after the account
+/// nonce and everything already queued.
+pub fn next_nonce(consensus_engine: &ConsensusEngine, address: &str) -> u64 {
+    let account_nonce = consensus_engine.ledger.lock().get(address).map(|account| account.nonce).unwrap_or(0);
+    let mempool_lock = consensus_engine.mempool.lock();
+    let mut nonce = account_nonce;
+    while mempool_lock.has_nonce(address, nonce) {
+        nonce += 1;
+    }
+    nonce
+}
+
+/// Mempool transactions that can be executed now, highest fee first: for each
+/// sender, the run of consecutive nonces starting at its account nonce.
+/// Expired transactions are dropped first; those behind a nonce gap stay queued.
+pub fn executable_transactions(consensus_engine: &ConsensusEngine) -> Vec<Transaction> {
+    let ledger = consensus_engine.ledger.lock();
+    let mut mempool_lock = consensus_engine.mempool.lock();
+    mempool_lock.expire(Utc::now().timestamp());
+    mempool_lock.executable(&ledger)
+}
+
+/// Queued transactions from `sender`, lowest nonce first.
+pub fn pending_transactions(consensus_engine: &ConsensusEngine, sender: &str) -> Vec<Transaction> {
+    consensus_engine.mempool.lock().by_sender(sender)
+}
+
 pub fn cancel_transaction(consensus_engine: &mut ConsensusEngine, tx_hash: String) -> Result<(), String> {
     let mut mempool_lock = consensus_engine.mempool.lock();
-    let pos = mempool_lock.iter().position(|tx| tx.hash == tx_hash);
 
-    if let Some(index) = pos {
-        mempool_lock.remove(index);
+    if mempool_lock.remove(&tx_hash).is_some() {
         drop(mempool_lock);
 
         consensus_engine.persist_state();
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::core::consensus::block::{hash_block, validate_block};
+use crate::core::consensus::execution::{BlockOutcome, applied_outcome, apply_block, prune_pending, revert_block};
+use crate::core::consensus::epoch::prune_validator_sets;
+use crate::core::consensus::finality::finalized_height;
+use crate::core::consensus::model::ConsensusEngine;
+use crate::core::types::Block;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BlockNode {
+    pub block: Block,
+    pub height: u64,
+}
+
+/// Every valid block we know of, keyed by hash, including blocks on side
+/// branches. `ConsensusEngine::chain` is the branch chosen by fork choice.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BlockTree {
+    pub nodes: HashMap<[u8; 32], BlockNode>,
+}
+
+impl BlockTree {
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.nodes.contains_key(hash)
+    }
+
+    pub fn get(&self, hash: &[u8; 32]) -> Option<&BlockNode> {
+        self.nodes.get(hash)
+    }
+
+    /// Adds a block whose parent is already in the tree, or a genesis block when
+    /// the tree is empty, and returns its height.
+    pub fn insert(&mut self, block: Block) -> Result<u64, String> {
+        let block_hash = hash_block(&block);
+        if self.nodes.contains_key(&block_hash) {
+            return Err("Block already known".into());
+        }
+
+        let height = if block.header.parent_hash == [0u8; 32] {
+            if self.nodes.values().any(|node| node.height == 0) {
+                return Err("Conflicting genesis block".into());
+            }
+            0
+        } else {
+            let parent = self.nodes.get(&block.header.parent_hash).ok_or("Unknown parent block")?;
+            parent.height + 1
+        };
+
+        self.nodes.insert(block_hash, BlockNode { block, height });
+        Ok(height)
+    }
+
+    /// Whether `hash` is `ancestor` or one of its descendants.
+    pub fn extends(&self, hash: &[u8; 32], ancestor: &[u8; 32]) -> bool {
+        let Some(ancestor_height) = self.nodes.get(ancestor).map(|node| node.height) else {
+            return false;
+        };
+
+        let mut cursor = *hash;
+        while let Some(node) = self.nodes.get(&cursor) {
+            if node.height <= ancestor_height {
+                return cursor == *ancestor;
+            }
+            cursor = node.block.header.parent_hash;
+        }
+        false
+    }
+
+    /// Removes a block and every descendant of it.
+    pub fn remove_branch(&mut self, hash: &[u8; 32]) {
+        let mut removed = vec![*hash];
+        while let Some(parent) = removed.pop() {
+            self.nodes.remove(&parent);
+            removed.extend(
+                self.nodes
+                    .iter()
+                    .filter(|(_, node)| node.block.header.parent_hash == parent)
+                    .map(|(child, _)| *child),
+            );
+        }
+    }
+
+    /// Drops every block that does not descend from the finalized block or
+    /// sit below it on the canonical chain; such blocks can never become
+    /// canonical again.
+    pub fn prune(&mut self, canonical: &[Block], finalized_height: u64) {
+        let Some(finalized) = canonical.get(finalized_height as usize).map(hash_block) else {
+            return;
+        };
+        let keep: Vec<[u8; 32]> = self
+            .nodes
+            .iter()
+            .filter(|(hash, node)| {
+                if node.height > finalized_height {
+                    self.extends(hash, &finalized)
+                } else {
+                    canonical.get(node.height as usize).is_some_and(|blk| hash_block(blk) == **hash)
+                }
+            })
+            .map(|(hash, _)| *hash)
+            .collect();
+        self.nodes.retain(|hash, _| keep.contains(hash));
+    }
+}
+
+/// Adds a validated block to the block tree and runs fork choice. Returns `true`
+/// if the block ended up on the canonical chain.
+pub fn insert_block(consensus_engine: &mut ConsensusEngine, block: Block) -> Result<bool, String> {
+    let block_hash = hash_block(&block);
+    consensus_engine.tree.lock().insert(block)?;
+
+    update_head(consensus_engine, &block_hash)?;
+
+    let chain_lock = consensus_engine.chain.lock();
+    Ok(chain_lock.iter().rev().any(|blk| hash_block(blk) == block_hash))
+}
+
+/// Fork choice: the longest branch that contains the latest finalized block
+/// wins, and ties keep the current head.
+fn update_head(consensus_engine: &mut ConsensusEngine, candidate: &[u8; 32]) -> Result<(), String> {
+    {
+        let tree_lock = consensus_engine.tree.lock();
+        let chain_lock = consensus_engine.chain.lock();
+
+        let candidate_height = tree_lock.get(candidate).ok_or("Unknown block")?.height;
+        if !chain_lock.is_empty() && candidate_height < chain_lock.len() as u64 {
+            return Ok(());
+        }
+    }
+
+    switch_head(consensus_engine, candidate)
+}
+
+/// Moves the head onto the branch of `certified`, a block a finality
+/// certificate has made final, even if that branch is shorter: the head
+/// becomes the longest known descendant of `certified`. A descendant that
+/// fails validation is dropped and the next best one tried.
+pub fn switch_to_certified(consensus_engine: &mut ConsensusEngine, certified: &[u8; 32]) -> Result<(), String> {
+    loop {
+        let best = {
+            let tree_lock = consensus_engine.tree.lock();
+            let chain_lock = consensus_engine.chain.lock();
+            if chain_lock.last().is_some_and(|tip| tree_lock.extends(&hash_block(tip), certified)) {
+                return Ok(());
+            }
+
+            tree_lock
+                .nodes
+                .iter()
+                .filter(|(hash, _)| tree_lock.extends(hash, certified))
+                .max_by_key(|(hash, node)| (node.height, std::cmp::Reverse(**hash)))
+                .map(|(hash, _)| *hash)
+                .ok_or("Certified block is not in the block tree")?
+        };
+
+        match switch_head(consensus_engine, &best) {
+            Ok(()) => return Ok(()),
+            Err(err) if best == *certified => return Err(err),
+            Err(err) => eprintln!("❌ Skipping invalid descendant of a certified block: {}", err),
+        }
+    }
+}
+
+/// Makes `candidate` the head. Switching branches rolls the canonical chain
+/// back to the common ancestor, which must lie above the finalized height,
+/// and re-applies the new branch, fully validating each block against the
+/// state rebuilt below it. Any failure restores the previous head and drops
+/// the rest of the branch.
+fn switch_head(consensus_engine: &mut ConsensusEngine, candidate: &[u8; 32]) -> Result<(), String> {
+    let (branch, fork_height) = {
+        let tree_lock = consensus_engine.tree.lock();
+        let chain_lock = consensus_engine.chain.lock();
+
+        let mut branch = Vec::new();
+        let mut cursor = *candidate;
+        let fork_height = loop {
+            let node = tree_lock.get(&cursor).ok_or("Block tree is missing an ancestor")?;
+            if chain_lock.get(node.height as usize).is_some_and(|blk| hash_block(blk) == cursor) {
+                break node.height + 1;
+            }
+
+            branch.push(node.block.clone());
+            if node.block.header.parent_hash == [0u8; 32] {
+                break 0;
+            }
+            cursor = node.block.header.parent_hash;
+        };
+
+        branch.reverse();
+        (branch, fork_height)
+    };
+
+    if branch.is_empty() {
+        return Ok(());
+    }
+
+    if let Some(finalized) = finalized_height(consensus_engine)
+        && fork_height <= finalized
+    {
+        return Err(format!("Branch forks at height {} below finalized height {}", fork_height, finalized));
+    }
+
+    let rolled_back = rollback_to(consensus_engine, fork_height)?;
+
+    let applied = branch.len();
+    for (index, block) in branch.into_iter().enumerate() {
+        let block_hash = hash_block(&block);
+        let height = block.header.height;
+
+        // A block extending the old tip was validated on import; every other
+        // one is seen here for the first time with its parent's state.
+        let validated = if index == 0 && rolled_back.is_empty() {
+            Ok(())
+        } else {
+            validate_block(consensus_engine, &block)
+        };
+        let result = validated.and_then(|()| {
+            let expected = BlockOutcome::of_header(&block.header);
+            connect_block(consensus_engine, block.clone());
+            if applied_outcome(consensus_engine, &block) != expected {
+                return Err("State root, ledger root or fees mismatch".to_string());
+            }
+            Ok(())
+        });
+
+        if let Err(err) = result {
+            // Restore the previous head and forget the invalid branch.
+            rollback_to(consensus_engine, fork_height)?;
+            for old_block in rolled_back.into_iter().rev() {
+                connect_block(consensus_engine, old_block);
+            }
+            prune_pending(consensus_engine);
+            consensus_engine.tree.lock().remove_branch(&block_hash);
+            consensus_engine.persist_state();
+            return Err(format!("Invalid block at height {} on the new branch: {}", height, err));
+        }
+    }
+    prune_pending(consensus_engine);
+
+    if !rolled_back.is_empty() {
+        println!(
+            "🔀 Chain reorganized at height {}: rolled back {} blocks, applied {}",
+            fork_height, rolled_back.len(), applied
+        );
+    }
+
+    consensus_engine.persist_state();
+    Ok(())
+}
+
+fn connect_block(consensus_engine: &mut ConsensusEngine, block: Block) {
+    let undo = apply_block(consensus_engine, &block);
+    let block_hash = hash_block(&block);
+
+    let serialized = bincode::serialize(&undo).unwrap();
+    let key = format!("undo_{}", hex::encode(block_hash));
+    consensus_engine.db.put(key.as_bytes(), serialized).unwrap();
+
+    consensus_engine.undo.lock().insert(block_hash, undo);
+    consensus_engine.chain.lock().push(block);
+}
+
+/// Pops canonical blocks above `height`, reverting each one's effects. Returns
+/// the removed blocks, highest first.
+fn rollback_to(consensus_engine: &mut ConsensusEngine, height: u64) -> Result<Vec<Block>, String> {
+    let mut rolled_back = Vec::new();
+    loop {
+        let block = {
+            let chain_lock = consensus_engine.chain.lock();
+            if chain_lock.len() as u64 <= height {
+                break;
+            }
+            chain_lock.last().cloned()
+        };
+        let Some(block) = block else {
+            break;
+        };
+
+        let block_hash = hash_block(&block);
+        let undo = consensus_engine
+            .undo
+            .lock()
+            .remove(&block_hash)
+            .ok_or("Missing undo record for canonical block")?;
+        let key = format!("undo_{}", hex::encode(block_hash));
+        consensus_engine.db.delete(key.as_bytes()).unwrap();
+
+        revert_block(consensus_engine, &block, undo);
+
+        let mut chain_lock = consensus_engine.chain.lock();
+        chain_lock.pop();
+        let key = format!("block_{}", chain_lock.len());
+        consensus_engine.db.delete(key.as_bytes()).unwrap();
+        rolled_back.push(block);
+    }
+
+    Ok(rolled_back)
+}
+
+/// Forgets undo records and side branches that finality has made irrelevant.
+pub fn prune_finalized(consensus_engine: &mut ConsensusEngine, finalized_height: u64) {
+    {
+        let chain_lock = consensus_engine.chain.lock();
+        consensus_engine.tree.lock().prune(&chain_lock, finalized_height);
+    }
+    prune_validator_sets(consensus_engine);
+
+    let chain_lock = consensus_engine.chain.lock();
+
+    let mut undo_lock = consensus_engine.undo.lock();
+    for blk in chain_lock.iter().take(finalized_height as usize + 1) {
+        let block_hash = hash_block(blk);
+        if undo_lock.remove(&block_hash).is_some() {
+            let key = format!("undo_{}", hex::encode(block_hash));
+            consensus_engine.db.delete(key.as_bytes()).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::consensus::block::{import_block, serialize_header_for_signing};
+    use crate::core::consensus::transaction::add_transaction;
+    use crate::core::crypto::sign_data;
+    use crate::core::test_utils::{address_of, build_block, signing_key, test_engine, transfer};
+    use crate::core::types::Transaction;
+
+    fn tip(consensus_engine: &ConsensusEngine) -> [u8; 32] {
+        consensus_engine.chain.lock().last().map(hash_block).unwrap()
+    }
+
+    /// Two engines sharing a genesis block. The first holds a one-block
+    /// branch with a transfer, the second a two-block empty branch.
+    fn forked() -> (ConsensusEngine, ConsensusEngine, Vec<Block>, Transaction) {
+        let keys = [signing_key(1), signing_key(2)];
+        let mut first = test_engine("tree_first", &keys, 1_000, 1_000_000);
+        let mut second = test_engine("tree_second", &keys, 1_000, 1_000_000);
+
+        let genesis = build_block(&first, &keys, 1);
+        import_block(&mut first, genesis.clone()).unwrap();
+        import_block(&mut second, genesis).unwrap();
+
+        let tx = transfer(&mut first, &keys[0], &keys[1], 500);
+        add_transaction(&mut first, tx.clone()).unwrap();
+        let block = build_block(&first, &keys, 2);
+        import_block(&mut first, block).unwrap();
+        assert!(!first.mempool.lock().contains(&tx.hash));
+
+        let mut branch = Vec::new();
+        for slot in [3, 4] {
+            let block = build_block(&second, &keys, slot);
+            import_block(&mut second, block.clone()).unwrap();
+            branch.push(block);
+        }
+        (first, second, branch, tx)
+    }
+
+    #[test]
+    fn longer_branch_reorgs_and_requeues_transactions() {
+        let (mut first, second, branch, tx) = forked();
+        let old_tip = tip(&first);
+
+        import_block(&mut first, branch[0].clone()).unwrap();
+        assert_eq!(tip(&first), old_tip);
+
+        import_block(&mut first, branch[1].clone()).unwrap();
+        assert_eq!(tip(&first), tip(&second));
+        assert!(first.tree.lock().contains(&old_tip));
+        assert!(!first.undo.lock().contains_key(&old_tip));
+        assert!(first.mempool.lock().contains(&tx.hash));
+        assert_eq!(first.ledger_tree.lock().root(), second.ledger_tree.lock().root());
+        for (address, account) in second.ledger.lock().iter() {
+            let ledger_lock = first.ledger.lock();
+            let mirrored = &ledger_lock[address];
+            assert_eq!((mirrored.balance, mirrored.nonce), (account.balance, account.nonce));
+        }
+    }
+
+    #[test]
+    fn invalid_branch_restores_previous_head() {
+        let (mut first, _second, mut branch, tx) = forked();
+        let old_tip = tip(&first);
+        let balance = first.get_balance(&tx.from);
+
+        // Re-sign the second block with a wrong state root; it only fails once
+        // it is executed on top of its parent during the reorg.
+        let keys = [signing_key(1), signing_key(2)];
+        let header = &mut branch[1].header;
+        let key = keys.iter().find(|key| address_of(key) == header.validator).unwrap();
+        header.state_root = [7u8; 32];
+        header.signature = sign_data(key, &serialize_header_for_signing(header).unwrap());
+        let bad = hash_block(&branch[1]);
+
+        import_block(&mut first, branch[0].clone()).unwrap();
+        assert!(import_block(&mut first, branch[1].clone()).is_err());
+        assert_eq!(tip(&first), old_tip);
+        assert_eq!(first.get_balance(&tx.from), balance);
+        assert!(!first.mempool.lock().contains(&tx.hash));
+        assert!(!first.tree.lock().contains(&bad));
+        assert!(first.tree.lock().contains(&hash_block(&branch[0])));
+    }
+}
@@ -1,47 +1,27 @@
 use std::sync::Arc;
 use crate::core::types::{Address, MutexWrapper};
 use sha2::{Digest, Sha256};
-use chrono::Utc;
-use rand::Rng;
 use serde::{Deserialize, Serialize};
+use crate::core::consensus::block::hash_block;
+use crate::core::consensus::epoch::validator_set_at;
 use crate::core::consensus::model::ConsensusEngine;
+use crate::core::consensus::slot::next_slot;
 use crate::core::nervous::NervousSystem;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct ValidatorInfo {
     pub address: Address,
     pub neuron_id: u64,
     pub active: bool,
+    /// Slots this validator led and filled with a canonical block.
+    pub produced_slots: u64,
+    /// Slots this validator led that passed without its block.
+    pub missed_slots: u64,
+    /// Missed slots that still fall inside the liveness window.
+    pub recent_misses: Vec<u64>,
+    /// Height of the block that jailed the validator, while it is jailed.
+    pub jailed_at: Option<u64>,
 }
-pub fn slash(
-    consensus_engine: &mut ConsensusEngine,
-    validator: Address,
-    amount: u64,
-) {
-    {
-        let penalty = amount * 2;
-        let mut neurons = consensus_engine.neurons.lock();
-
-        let mut validators_lock = consensus_engine.validators.lock();
-
-        for neuron in neurons.values_mut() {
-            if neuron.validator == Some(validator.clone()) {
-                if neuron.staked_amount > penalty {
-                    neuron.staked_amount -= penalty;
-                } else {
-                    neuron.staked_amount = 0;
-                }
-            }
-        }
-
-        if let Some(v) = validators_lock.iter_mut().find(|v| v.address == validator) {
-            v.active = false;
-        }
-    }
-
-    consensus_engine.persist_state();
-}
-
 pub fn build_validator(nervous_system: &mut NervousSystem, neuron_id: u64) -> Result<ValidatorInfo, String> {
     let neurons_lock = nervous_system.neurons.lock();
     let neuron = neurons_lock.get(&neuron_id).ok_or("Neuron not exist")?;
@@ -50,6 +30,7 @@ pub fn build_validator(nervous_system: &mut NervousSystem, neuron_id: u64) -> Re
         address: neuron.address.clone(),
         neuron_id,
         active: false,
+        ..Default::default()
     };
 
     drop(neurons_lock);
@@ -72,7 +53,7 @@ pub fn register_validator(consensus_engine: &mut ConsensusEngine, neuron_id: u64
             return Err("Neuron already exists".into());
         }
 
-        if neuron.staked_amount < 100 {
+        if neuron.staked_amount < consensus_engine.config.min_self_stake {
             return Err("Neuron does not have enough stake".into());
         }
 
@@ -80,50 +61,93 @@ pub fn register_validator(consensus_engine: &mut ConsensusEngine, neuron_id: u64
             address: neuron.address.clone(),
             neuron_id,
             active: true,
+            ..Default::default()
         });
     }
 
     consensus_engine.persist_state();
     Ok(())
 }
-pub fn select_next_validator(
-    consensus_engine: &mut ConsensusEngine,
-) -> Option<Address> {
-    let now = Utc::now().timestamp_nanos_opt().unwrap_or(0);
-    let neurons_lock = consensus_engine.neurons.lock();
-    let validators_lock = consensus_engine.validators.lock();
-
-    let stake_weighted: Vec<(Address, u64)> = neurons_lock
-        .values()
-        .filter_map(|neuron| {
-            neuron.validator.as_ref().and_then(|validator_address| {
-                validators_lock.iter().find(|v| v.address == *validator_address)
-                    .map(|validator| (validator.address.clone(), neuron.staked_amount))
-            })
-        })
-        .collect();
+/// Stake backing each validator of the epoch containing `height` on the
+/// branch of `parent_hash`, sorted by address so every node iterates the set
+/// in the same order.
+pub fn stake_weights(consensus_engine: &ConsensusEngine, parent_hash: &[u8; 32], height: u64) -> Result<Vec<(Address, u64)>, String> {
+    Ok(validator_set_at(consensus_engine, parent_hash, height)?.validators)
+}
 
+/// Validators as they stood right after `parent_hash` was applied, read from
+/// the undo record of its canonical child. `None` if the parent is not on the
+/// canonical chain, since the state of other branches is not kept.
+pub fn parent_validators(consensus_engine: &ConsensusEngine, parent_hash: &[u8; 32]) -> Option<Vec<ValidatorInfo>> {
+    let chain_lock = consensus_engine.chain.lock();
+    let child = if *parent_hash == [0u8; 32] {
+        chain_lock.first()
+    } else {
+        let index = chain_lock.iter().rposition(|blk| hash_block(blk) == *parent_hash)?;
+        chain_lock.get(index + 1)
+    };
 
-    if stake_weighted.is_empty() {
-        return None;
+    match child {
+        Some(child) => consensus_engine.undo.lock().get(&hash_block(child))?.validators.clone(),
+        None => Some(consensus_engine.validators.lock().clone()),
     }
+}
 
+/// Picks the producer for `slot` on top of `parent_hash` from the validator
+/// set of the epoch containing `height`, skipping validators that were jailed
+/// once the parent was applied. The roll is derived from
+/// `sha256(parent_hash || slot)` and weighted by stake, so every node
+/// computes the same leader. `None` if no validator is eligible or the parent
+/// is not on the canonical chain.
+pub fn select_leader(
+    consensus_engine: &ConsensusEngine,
+    parent_hash: &[u8; 32],
+    height: u64,
+    slot: u64,
+) -> Option<Address> {
+    let jailed: Vec<Address> = parent_validators(consensus_engine, parent_hash)?
+        .iter()
+        .filter(|validator| validator.jailed_at.is_some())
+        .map(|validator| validator.address.clone())
+        .collect();
+    let stake_weighted: Vec<(Address, u64)> = stake_weights(consensus_engine, parent_hash, height)
+        .ok()?
+        .into_iter()
+        .filter(|(address, _)| !jailed.contains(address))
+        .collect();
     let total_stake: u64 = stake_weighted.iter().map(|(_, stake)| stake).sum();
-    let seed = now.wrapping_add(rand::thread_rng().gen_range(0..total_stake as i64));
-    let hash = Sha256::digest(&seed.to_be_bytes());
+    if total_stake == 0 {
+        return None;
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(parent_hash);
+    hasher.update(slot.to_be_bytes());
+    let hash = hasher.finalize();
     let roll = u64::from_be_bytes(hash[0..8].try_into().unwrap()) % total_stake;
 
     let mut cumulative = 0;
     for (validator, stake) in stake_weighted {
         cumulative += stake;
         if roll < cumulative {
-            drop(validators_lock);
-            drop(neurons_lock);
-
-            consensus_engine.persist_state();
             return Some(validator);
         }
     }
 
     None
-}
\ No newline at end of file
+}
+
+/// Leader of the next unfilled slot on top of the current chain tip.
+pub fn select_next_validator(
+    consensus_engine: &mut ConsensusEngine,
+) -> Option<Address> {
+    let slot = next_slot(consensus_engine);
+    let (parent_hash, height) = {
+        let chain_lock = consensus_engine.chain.lock();
+        let parent_hash = chain_lock.last().map(hash_block).unwrap_or([0u8; 32]);
+        let height = chain_lock.last().map(|blk| blk.header.height + 1).unwrap_or(0);
+        (parent_hash, height)
+    };
+
+    select_leader(consensus_engine, &parent_hash, height, slot)
+}
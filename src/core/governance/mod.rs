@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod governance;
 pub mod proposal;
 pub mod proposal_handler;
@@ -68,7 +68,7 @@ pub fn vote(
         if let Some(proposal) = found {
             heap.push(proposal);
         } else {
-            return Err("Proposal not found".to_string()).expect("Couldn't return an error message");
+            return Err("Proposal not found".to_string());
         }
     }
 
@@ -7,7 +7,12 @@ pub mod crypto;
 pub mod governance;
 pub mod nervous;
 pub mod staking;
+pub mod subnet;
 pub mod types;
 pub mod wallet;
+pub mod xnet;
 
 pub mod network;
+
+#[cfg(test)]
+pub mod test_utils;
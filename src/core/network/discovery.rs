@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use chrono::Utc;
+use rocksdb::DB;
+use serde::{Deserialize, Serialize};
+use crate::core::types::MutexWrapper;
+
+const BASE_BACKOFF_SECS: i64 = 2;
+const MAX_BACKOFF_SECS: i64 = 300;
+/// Most addresses kept; beyond this the least reliable entry is evicted.
+const MAX_ADDRESS_BOOK_SIZE: usize = 1024;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KnownPeer {
+    pub address: String,
+    pub failures: u32,
+    pub last_seen: i64,
+    pub next_attempt: i64,
+}
+
+/// Dialable peer addresses learned from bootstrap config and peer exchange,
+/// with the reconnect backoff state for each one.
+#[derive(Clone)]
+pub struct AddressBook {
+    pub peers: Arc<MutexWrapper<HashMap<String, KnownPeer>>>,
+    db: Arc<DB>,
+}
+
+impl AddressBook {
+    pub fn new(db: Arc<DB>) -> Self {
+        let book = Self {
+            peers: Arc::new(MutexWrapper::new(HashMap::new())),
+            db,
+        };
+        book.load_state();
+        book
+    }
+
+    pub fn add(&self, address: String) {
+        let mut peers = self.peers.lock();
+        if peers.contains_key(&address) {
+            return;
+        }
+
+        // When full, make room by dropping the peer that failed most often and
+        // was seen longest ago, but never one that is known to be healthy.
+        if peers.len() >= MAX_ADDRESS_BOOK_SIZE {
+            let victim = peers
+                .values()
+                .max_by_key(|peer| (peer.failures, std::cmp::Reverse(peer.last_seen)))
+                .filter(|peer| peer.failures > 0 || peer.last_seen == 0)
+                .map(|peer| peer.address.clone());
+            let Some(victim) = victim else {
+                return;
+            };
+            peers.remove(&victim);
+            let key = format!("peer_{}", victim);
+            self.db.delete(key.as_bytes()).unwrap();
+        }
+
+        let peer = KnownPeer {
+            address: address.clone(),
+            failures: 0,
+            last_seen: 0,
+            next_attempt: 0,
+        };
+        self.persist_peer(&peer);
+        peers.insert(address, peer);
+    }
+
+    pub fn record_success(&self, address: &str) {
+        let mut peers = self.peers.lock();
+        let peer = peers.entry(address.to_string()).or_insert_with(|| KnownPeer {
+            address: address.to_string(),
+            failures: 0,
+            last_seen: 0,
+            next_attempt: 0,
+        });
+
+        peer.failures = 0;
+        peer.last_seen = Utc::now().timestamp();
+        peer.next_attempt = 0;
+        self.persist_peer(peer);
+    }
+
+    pub fn record_failure(&self, address: &str) {
+        let mut peers = self.peers.lock();
+        if let Some(peer) = peers.get_mut(address) {
+            peer.failures = peer.failures.saturating_add(1);
+            let backoff = BASE_BACKOFF_SECS
+                .saturating_mul(1i64 << peer.failures.min(16))
+                .min(MAX_BACKOFF_SECS);
+            peer.next_attempt = Utc::now().timestamp() + backoff;
+            self.persist_peer(peer);
+        }
+    }
+
+    /// Addresses whose backoff has expired, least-failed first.
+    pub fn dial_candidates(&self) -> Vec<String> {
+        let now = Utc::now().timestamp();
+        let peers = self.peers.lock();
+        let mut candidates: Vec<&KnownPeer> = peers.values().filter(|peer| peer.next_attempt <= now).collect();
+        candidates.sort_by_key(|peer| peer.failures);
+        candidates.into_iter().map(|peer| peer.address.clone()).collect()
+    }
+
+    fn persist_peer(&self, peer: &KnownPeer) {
+        let serialized = bincode::serialize(peer).unwrap();
+        let key = format!("peer_{}", peer.address);
+        self.db.put(key.as_bytes(), serialized).unwrap();
+    }
+
+    pub fn load_state(&self) {
+        let mut peers = self.peers.lock();
+        peers.clear();
+        let iter = self.db.iterator(rocksdb::IteratorMode::Start);
+        for item in iter {
+            let (key, value) = item.unwrap();
+            if key.starts_with(b"peer_")
+                && let Ok(peer) = bincode::deserialize::<KnownPeer>(&value)
+            {
+                peers.insert(peer.address.clone(), peer);
+            }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+use rocksdb::DB;
+use serde::{Serialize, Deserialize};
+use bincode;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+
+/// Key pair a node uses to authenticate itself to peers. Kept separate from
+/// the wallet so that rotating one never exposes the other.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct NodeIdentity {
+    pub signing_key: SigningKey,
+    pub public_key: VerifyingKey,
+    pub node_id: String,
+}
+
+impl NodeIdentity {
+    pub fn new(db: Arc<DB>) -> Self {
+        if let Some(identity) = Self::load_state(&db) {
+            return identity;
+        }
+
+        let signing_key = SigningKey::generate(&mut rand::rngs::OsRng);
+        let public_key = VerifyingKey::from(&signing_key);
+        let node_id = hex::encode(public_key.to_bytes());
+
+        let identity = NodeIdentity { signing_key, public_key, node_id };
+        identity.persist_state(&db);
+
+        identity
+    }
+
+    pub fn persist_state(&self, db: &Arc<DB>) {
+        match bincode::serialize(self) {
+            Ok(serialized) => {
+                db.put(b"identity_node", serialized).expect("Failed to store node identity in database.");
+            }
+            Err(e) => {
+                eprintln!("Node identity serialization failed: {}", e);
+            }
+        }
+    }
+
+    pub fn load_state(db: &Arc<DB>) -> Option<Self> {
+        if let Ok(Some(data)) = db.get(b"identity_node") {
+            match bincode::deserialize::<NodeIdentity>(&data) {
+                Ok(identity) => Some(identity),
+                Err(e) => {
+                    eprintln!("Failed to deserialize node identity: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        }
+    }
+}
@@ -3,6 +3,7 @@ use std::sync::{Arc, Mutex};
 use rocksdb::DB;
 use bincode;
 use serde::{Serialize, Deserialize};
+use crate::core::types::decode_record;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Node {
@@ -16,6 +17,41 @@ pub struct Node {
     pub node_operator_id: String,
     pub subnet_id: String,
     pub ip_address: String,
+    /// Listening port; 0 for records migrated from before ports were stored.
+    pub port: u16,
+}
+
+/// Node record as persisted before `port` was added.
+#[derive(Deserialize)]
+struct LegacyNode {
+    data_center_owner: String,
+    fiber_state: String,
+    dc_id: String,
+    location: String,
+    node_provider: String,
+    status: String,
+    node_provider_id: String,
+    node_operator_id: String,
+    subnet_id: String,
+    ip_address: String,
+}
+
+impl From<LegacyNode> for Node {
+    fn from(legacy: LegacyNode) -> Self {
+        Node {
+            data_center_owner: legacy.data_center_owner,
+            fiber_state: legacy.fiber_state,
+            dc_id: legacy.dc_id,
+            location: legacy.location,
+            node_provider: legacy.node_provider,
+            status: legacy.status,
+            node_provider_id: legacy.node_provider_id,
+            node_operator_id: legacy.node_operator_id,
+            subnet_id: legacy.subnet_id,
+            ip_address: legacy.ip_address,
+            port: 0,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -44,6 +80,22 @@ impl NodeRegistry {
         self.db.put(key.as_bytes(), serialized).unwrap();
     }
 
+    /// Registers `node` unless a record with its id already exists. Returns
+    /// whether it was added.
+    pub fn register_new_node(&self, node: Node) -> bool {
+        {
+            let mut nodes = self.nodes.lock().unwrap();
+            if nodes.contains_key(&node.dc_id) {
+                return false;
+            }
+            nodes.insert(node.dc_id.clone(), node.clone());
+        }
+        let serialized = bincode::serialize(&node).unwrap();
+        let key = format!("node_{}", node.dc_id);
+        self.db.put(key.as_bytes(), serialized).unwrap();
+        true
+    }
+
     pub fn get_node(&self, id: &str) -> Option<Node> {
         let nodes = self.nodes.lock().unwrap();
         nodes.get(id).cloned()
@@ -70,10 +122,14 @@ impl NodeRegistry {
         let iter = self.db.iterator(rocksdb::IteratorMode::Start);
         for item in iter {
             let (key, value) = item.unwrap();
-            if key.starts_with(b"node_") {
-                if let Ok(node) = bincode::deserialize::<Node>(&value) {
+            if !key.starts_with(b"node_") {
+                continue;
+            }
+            match decode_record::<Node, LegacyNode>(&self.db, &key, &value) {
+                Ok(node) => {
                     nodes.insert(node.dc_id.clone(), node);
                 }
+                Err(err) => eprintln!("❌ Skipping node record: {}", err),
             }
         }
     }
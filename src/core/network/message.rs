@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+use crate::core::consensus::{BlockVote, FinalityCertificate};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use crate::core::network::machine::Node;
+use crate::core::types::{Block, BlockHeader, Evidence, Transaction, Unjail};
+use crate::core::xnet::{StreamAck, StreamSlice};
+
+pub const WIRE_VERSION: u8 = 1;
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+pub const MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Handshake {
+    pub protocol_version: u32,
+    pub chain_id: String,
+    pub subnet_id: String,
+    pub genesis_hash: [u8; 32],
+    pub best_height: u64,
+    pub node_id: String,
+    pub listen_port: u16,
+    pub nonce: [u8; 32],
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum NetworkMessage {
+    Hello(Handshake),
+    HelloProof(Vec<u8>),
+    Ping(u64),
+    Pong(u64),
+    Block(Box<Block>),
+    Transaction(Transaction),
+    AnnounceTransactions(Vec<String>),
+    GetTransactions(Vec<String>),
+    GetHeaders {
+        start_height: u64,
+        limit: u32,
+    },
+    Headers(Vec<BlockHeader>),
+    GetStatus,
+    Status {
+        best_height: u64,
+        best_hash: [u8; 32],
+    },
+    GetBlocks(Vec<u64>),
+    Blocks(Vec<Block>),
+    GetPeers,
+    Peers(Vec<Node>),
+    BlockVote(BlockVote),
+    FinalityCertificate(FinalityCertificate),
+    Evidence(Box<Evidence>),
+    Unjail(Unjail),
+    XNetSlice(StreamSlice),
+    XNetAck(StreamAck),
+}
+
+impl NetworkMessage {
+    /// Consensus traffic that is only exchanged between nodes of the same subnet.
+    pub fn is_subnet_scoped(&self) -> bool {
+        matches!(
+            self,
+            NetworkMessage::Block(_)
+                | NetworkMessage::Transaction(_)
+                | NetworkMessage::AnnounceTransactions(_)
+                | NetworkMessage::GetTransactions(_)
+                | NetworkMessage::BlockVote(_)
+                | NetworkMessage::FinalityCertificate(_)
+                | NetworkMessage::Evidence(_)
+                | NetworkMessage::Unjail(_)
+        )
+    }
+}
+
+pub fn encode_message(message: &NetworkMessage) -> Result<Vec<u8>, String> {
+    let body = bincode::serialize(message).map_err(|e| format!("Message serialization error: {}", e))?;
+
+    let mut payload = Vec::with_capacity(body.len() + 1);
+    payload.push(WIRE_VERSION);
+    payload.extend_from_slice(&body);
+
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(format!("Frame of {} bytes exceeds maximum of {} bytes", payload.len(), MAX_FRAME_SIZE));
+    }
+
+    Ok(payload)
+}
+
+pub fn decode_message(payload: &[u8]) -> Result<NetworkMessage, String> {
+    let (version, body) = payload.split_first().ok_or("Empty frame")?;
+
+    if *version != WIRE_VERSION {
+        return Err(format!("Unsupported wire version: {}", version));
+    }
+
+    bincode::deserialize(body).map_err(|e| format!("Message deserialization error: {}", e))
+}
+
+pub async fn write_message<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    message: &NetworkMessage,
+) -> io::Result<()> {
+    let payload = encode_message(message).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    writer.write_u32(payload.len() as u32).await?;
+    writer.write_all(&payload).await?;
+    writer.flush().await
+}
+
+/// Reads one length-prefixed frame. Returns `Ok(None)` when the peer closed
+/// the connection cleanly between frames.
+pub async fn read_message<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> io::Result<Option<NetworkMessage>> {
+    let len = match reader.read_u32().await {
+        Ok(len) => len as usize,
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    if len > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Frame of {} bytes exceeds maximum of {} bytes", len, MAX_FRAME_SIZE),
+        ));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+
+    decode_message(&payload)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -1 +1,16 @@
-pub mod machine;
\ No newline at end of file
+pub mod discovery;
+pub mod identity;
+pub mod machine;
+pub mod message;
+#[allow(clippy::module_inception)]
+pub mod network;
+pub mod reputation;
+pub mod sync;
+pub mod transport;
+pub mod xnet;
+
+pub use message::{NetworkMessage, read_message, write_message};
+pub use discovery::{AddressBook, KnownPeer};
+pub use identity::NodeIdentity;
+pub use network::{Network, NetworkConfig, Peer};
+pub use reputation::{Ban, Misbehaviour, PeerReputation};
@@ -0,0 +1,779 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use ed25519_dalek::VerifyingKey;
+use rand::RngCore;
+use tokio::io;
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::sync::oneshot;
+use tokio::time::{sleep, timeout, Duration};
+
+use crate::core::consensus::{
+    add_evidence, add_transaction, add_unjail, add_vote, detect_equivocation, finalized_height, has_voted, hash_block,
+    import_block, import_certificate, try_finalize, verify_block_stateless, verify_transaction,
+};
+use crate::core::consensus::model::ConsensusEngine;
+use crate::core::crypto::{sign_data, verify_data};
+use crate::core::network::discovery::AddressBook;
+use crate::core::network::identity::NodeIdentity;
+use crate::core::network::reputation::{Misbehaviour, PeerReputation, RateLimiter};
+use crate::core::subnet::SubnetRegistry;
+use crate::core::network::transport::{establish, noise_public_key, FrameReader, FrameWriter, Transport};
+use crate::core::network::machine::NodeRegistry;
+use crate::core::network::message::{
+    Handshake, NetworkMessage, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use crate::core::types::{MutexWrapper, Transaction, Unjail};
+use crate::core::xnet::XNetStreams;
+
+const MAX_HEADERS_PER_REQUEST: u32 = 2000;
+const MAX_KNOWN_TRANSACTIONS: usize = 10_000;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const PEER_MAINTENANCE_INTERVAL: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const MAX_BLOCKS_PER_REQUEST: usize = 128;
+
+#[derive(Clone, Debug)]
+pub struct NetworkConfig {
+    pub chain_id: String,
+    pub listen_port: u16,
+    pub public_address: String,
+    /// Registry id (`dc_id`) of this node's own record.
+    pub local_node_id: String,
+    pub target_peers: usize,
+    pub max_messages_per_second: u32,
+    pub require_encryption: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Peer {
+    pub node_id: String,
+    pub subnet_id: String,
+    pub address: String,
+    pub dial_address: String,
+    pub listen_port: u16,
+    pub protocol_version: u32,
+    pub best_height: u64,
+    pub sender: UnboundedSender<NetworkMessage>,
+}
+
+/// Connected peers keyed by their authenticated node id.
+pub type PeerMap = Arc<Mutex<HashMap<String, Peer>>>;
+
+/// Bounded set of transaction hashes already admitted to the mempool or seen in
+/// a block, used to stop relay loops. Rejected transactions are never recorded,
+/// so one refused for a transient reason (a nonce gap, a full pool) can still
+/// be fetched again once it would be accepted.
+#[derive(Default)]
+pub struct KnownHashes {
+    order: VecDeque<String>,
+    hashes: HashSet<String>,
+}
+
+impl KnownHashes {
+    pub fn contains(&self, hash: &str) -> bool {
+        self.hashes.contains(hash)
+    }
+
+    pub fn insert(&mut self, hash: String) -> bool {
+        if !self.hashes.insert(hash.clone()) {
+            return false;
+        }
+
+        self.order.push_back(hash);
+        if self.order.len() > MAX_KNOWN_TRANSACTIONS
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.hashes.remove(&oldest);
+        }
+        true
+    }
+}
+
+#[derive(Clone)]
+pub struct Network {
+    pub peers: PeerMap,
+    pub consensus_engine: ConsensusEngine,
+    pub node_registry: NodeRegistry,
+    pub subnet_registry: SubnetRegistry,
+    pub xnet: XNetStreams,
+    pub address_book: AddressBook,
+    pub reputation: PeerReputation,
+    pub known_transactions: Arc<MutexWrapper<KnownHashes>>,
+    pub pending_requests: Arc<MutexWrapper<HashMap<String, oneshot::Sender<NetworkMessage>>>>,
+    pub synced: Arc<AtomicBool>,
+    pub identity: NodeIdentity,
+    pub config: NetworkConfig,
+}
+
+impl Network {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        consensus_engine: ConsensusEngine,
+        node_registry: NodeRegistry,
+        subnet_registry: SubnetRegistry,
+        xnet: XNetStreams,
+        address_book: AddressBook,
+        reputation: PeerReputation,
+        identity: NodeIdentity,
+        config: NetworkConfig,
+    ) -> Self {
+        Self {
+            peers: Arc::new(Mutex::new(HashMap::new())),
+            consensus_engine,
+            node_registry,
+            subnet_registry,
+            xnet,
+            address_book,
+            reputation,
+            known_transactions: Arc::new(MutexWrapper::new(KnownHashes::default())),
+            pending_requests: Arc::new(MutexWrapper::new(HashMap::new())),
+            synced: Arc::new(AtomicBool::new(false)),
+            identity,
+            config,
+        }
+    }
+
+    pub async fn connect(&self, address: &str) -> io::Result<()> {
+        let stream = match TcpStream::connect(address).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                self.address_book.record_failure(address);
+                return Err(e);
+            }
+        };
+        let network = self.clone();
+        let address = address.to_string();
+
+        tokio::spawn(async move {
+            if let Err(e) = network.run_connection(stream, Some(address.clone())).await {
+                eprintln!("❌ Error handling connection to {}: {}", address, e);
+            }
+        });
+
+        Ok(())
+    }
+
+    pub async fn handle_connection(&self, stream: TcpStream) -> io::Result<()> {
+        self.run_connection(stream, None).await
+    }
+
+    async fn run_connection(&self, stream: TcpStream, dialed: Option<String>) -> io::Result<()> {
+        let addr = stream.peer_addr()?;
+        let ip = addr.ip().to_string();
+        if self.reputation.is_banned(&ip) {
+            return Err(handshake_error(format!("Peer {} is banned", ip)));
+        }
+        let initiator = dialed.is_some();
+        let (transport, handshake) = match timeout(HANDSHAKE_TIMEOUT, self.open_session(stream, initiator)).await {
+            Ok(Ok(session)) => session,
+            Ok(Err(e)) => {
+                if let Some(dialed) = &dialed {
+                    self.address_book.record_failure(dialed);
+                }
+                return Err(e);
+            }
+            Err(_) => {
+                if let Some(dialed) = &dialed {
+                    self.address_book.record_failure(dialed);
+                }
+                return Err(handshake_error("Handshake timed out".to_string()));
+            }
+        };
+
+        let dial_address = dialed.unwrap_or_else(|| SocketAddr::new(addr.ip(), handshake.listen_port).to_string());
+        if dial_address != self.config.public_address {
+            self.address_book.record_success(&dial_address);
+        }
+        let addr = addr.to_string();
+        let peer_id = handshake.node_id.clone();
+        let Transport { mut reader, mut writer, remote_static } = transport;
+        let encryption = if remote_static.is_some() { "encrypted" } else { "plaintext" };
+
+        let (sender, mut receiver) = mpsc::unbounded_channel::<NetworkMessage>();
+        {
+            let mut peers_map = self.peers.lock().await;
+            if peers_map.contains_key(&peer_id) {
+                return Err(handshake_error(format!("Peer {} is already connected", peer_id)));
+            }
+            peers_map.insert(peer_id.clone(), Peer {
+                node_id: peer_id.clone(),
+                subnet_id: handshake.subnet_id.clone(),
+                address: addr.clone(),
+                dial_address,
+                listen_port: handshake.listen_port,
+                protocol_version: handshake.protocol_version,
+                best_height: handshake.best_height,
+                sender: sender.clone(),
+            });
+        }
+        println!("🔗 Peer connected: {} ({}, {}) at height {}", peer_id, addr, encryption, handshake.best_height);
+
+        let _ = sender.send(NetworkMessage::GetPeers);
+
+        let pending_hashes = self.mempool_hashes();
+        if !pending_hashes.is_empty() {
+            let _ = sender.send(NetworkMessage::AnnounceTransactions(pending_hashes));
+        }
+
+        let writer_peer = peer_id.clone();
+        let writer_task = tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                if let Err(e) = writer.write_message(&message).await {
+                    eprintln!("❌ Error writing to peer {}: {}", writer_peer, e);
+                    break;
+                }
+            }
+        });
+
+        let mut rate_limiter = RateLimiter::new(self.config.max_messages_per_second);
+        loop {
+            match reader.read_message().await {
+                Ok(Some(message)) => {
+                    // Throttle before filtering so peers of other subnets
+                    // cannot flood us with messages we would ignore.
+                    if !rate_limiter.allow() {
+                        self.reputation.report(&peer_id, &ip, Misbehaviour::RateLimited);
+                    } else if !message.is_subnet_scoped() || handshake.subnet_id == self.consensus_engine.subnet_id {
+                        self.handle_message(&peer_id, message, &sender).await;
+                    }
+                }
+                Ok(None) => {
+                    println!("🔌 Peer {} disconnected", peer_id);
+                    break;
+                }
+                Err(e) => {
+                    if e.kind() == io::ErrorKind::InvalidData {
+                        self.reputation.report(&peer_id, &ip, Misbehaviour::MalformedMessage);
+                    }
+                    eprintln!("❌ Error reading from peer {}: {}", peer_id, e);
+                    break;
+                }
+            }
+
+            if self.reputation.is_banned(&ip) {
+                println!("🚫 Disconnecting banned peer {} ({})", peer_id, ip);
+                break;
+            }
+        }
+
+        writer_task.abort();
+        {
+            let mut peers_map = self.peers.lock().await;
+            peers_map.remove(&peer_id);
+        }
+        self.pending_requests.lock().remove(&peer_id);
+        println!("🔌 Connection closed with peer: {} ({})", peer_id, addr);
+        Ok(())
+    }
+
+    async fn open_session(&self, stream: TcpStream, initiator: bool) -> io::Result<(Transport, Handshake)> {
+        let mut transport = establish(stream, &self.identity, initiator, self.config.require_encryption).await?;
+        let handshake = self
+            .perform_handshake(&mut transport.reader, &mut transport.writer, transport.remote_static)
+            .await?;
+        Ok((transport, handshake))
+    }
+
+    /// Exchanges `Hello` messages, rejects peers on another chain or an incompatible
+    /// protocol version, then has both sides sign the other's nonce with their node key.
+    /// On encrypted transports the Noise static key must belong to the claimed node id.
+    async fn perform_handshake(
+        &self,
+        reader: &mut FrameReader,
+        writer: &mut FrameWriter,
+        remote_static: Option<[u8; 32]>,
+    ) -> io::Result<Handshake> {
+        let local = self.local_handshake();
+        writer.write_message(&NetworkMessage::Hello(local.clone())).await?;
+
+        let remote = match reader.read_message().await? {
+            Some(NetworkMessage::Hello(remote)) => remote,
+            _ => return Err(handshake_error("Expected Hello message".to_string())),
+        };
+        self.check_handshake(&local, &remote)?;
+
+        let remote_key = parse_node_id(&remote.node_id).map_err(handshake_error)?;
+        if let Some(remote_static) = remote_static
+            && remote_static != noise_public_key(&remote_key)
+        {
+            return Err(handshake_error(format!("Noise key of {} is not bound to its node id", remote.node_id)));
+        }
+
+        let proof = sign_data(
+            &self.identity.signing_key,
+            &handshake_signing_payload(&remote.nonce, &local.node_id, &local.chain_id),
+        );
+        writer.write_message(&NetworkMessage::HelloProof(proof)).await?;
+
+        let remote_proof = match reader.read_message().await? {
+            Some(NetworkMessage::HelloProof(signature)) => signature,
+            _ => return Err(handshake_error("Expected HelloProof message".to_string())),
+        };
+
+        let payload = handshake_signing_payload(&local.nonce, &remote.node_id, &local.chain_id);
+        if !verify_data(&remote_key, &payload, &remote_proof) {
+            return Err(handshake_error(format!("Peer {} failed identity proof", remote.node_id)));
+        }
+
+        Ok(remote)
+    }
+
+    fn local_handshake(&self) -> Handshake {
+        let mut nonce = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+        let chain = self.consensus_engine.chain.lock();
+        Handshake {
+            protocol_version: PROTOCOL_VERSION,
+            chain_id: self.config.chain_id.clone(),
+            subnet_id: self.consensus_engine.subnet_id.clone(),
+            genesis_hash: chain.first().map(hash_block).unwrap_or([0u8; 32]),
+            best_height: chain.last().map(|block| block.header.height + 1).unwrap_or(0),
+            node_id: self.identity.node_id.clone(),
+            listen_port: self.config.listen_port,
+            nonce,
+        }
+    }
+
+    fn check_handshake(&self, local: &Handshake, remote: &Handshake) -> io::Result<()> {
+        if remote.protocol_version < MIN_PROTOCOL_VERSION || remote.protocol_version > PROTOCOL_VERSION {
+            return Err(handshake_error(format!(
+                "Incompatible protocol version {} (supported {}-{})",
+                remote.protocol_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            )));
+        }
+
+        if remote.chain_id != local.chain_id {
+            return Err(handshake_error(format!("Peer is on chain {}, expected {}", remote.chain_id, local.chain_id)));
+        }
+
+        let no_genesis = [0u8; 32];
+        if remote.subnet_id == local.subnet_id
+            && remote.genesis_hash != no_genesis && local.genesis_hash != no_genesis && remote.genesis_hash != local.genesis_hash {
+            return Err(handshake_error(format!(
+                "Genesis mismatch: peer has {}, expected {}",
+                hex::encode(remote.genesis_hash),
+                hex::encode(local.genesis_hash)
+            )));
+        }
+
+        if remote.node_id == local.node_id {
+            return Err(handshake_error("Refusing connection to self".to_string()));
+        }
+
+        Ok(())
+    }
+
+    async fn handle_message(
+        &self,
+        peer_id: &str,
+        message: NetworkMessage,
+        reply: &UnboundedSender<NetworkMessage>,
+    ) {
+        match message {
+            NetworkMessage::Ping(nonce) => {
+                let _ = reply.send(NetworkMessage::Pong(nonce));
+            }
+            NetworkMessage::Pong(_) => {}
+            NetworkMessage::Hello(_) | NetworkMessage::HelloProof(_) => {
+                eprintln!("❌ Unexpected handshake message from {}", peer_id);
+            }
+            NetworkMessage::Block(block) => {
+                let mut consensus_engine = self.consensus_engine.clone();
+                {
+                    let tree_lock = consensus_engine.tree.lock();
+                    if tree_lock.contains(&hash_block(&block)) {
+                        return;
+                    }
+                }
+
+                if let Some(evidence) = detect_equivocation(&mut consensus_engine, &block.header) {
+                    self.broadcast(NetworkMessage::Evidence(Box::new(evidence))).await;
+                }
+
+                {
+                    let tree_lock = consensus_engine.tree.lock();
+                    if block.header.parent_hash != [0u8; 32] && !tree_lock.contains(&block.header.parent_hash) {
+                        // We are behind or on another fork; the sync loop will fetch what is missing.
+                        return;
+                    }
+                }
+
+                if let Err(err) = verify_block_stateless(&consensus_engine, &block) {
+                    eprintln!("❌ Invalid block from {}: {}", peer_id, err);
+                    self.penalize(peer_id, Misbehaviour::InvalidBlock).await;
+                    return;
+                }
+
+                // Anything else depends on our chain or clock (a slot we have
+                // not reached, a leader we compute from another branch), which
+                // an honest peer can disagree on, so it is dropped unpunished.
+                match import_block(&mut consensus_engine, (*block).clone()) {
+                    Ok(()) => {
+                        if let Ok(Some(certificate)) = try_finalize(&mut consensus_engine, &hash_block(&block)) {
+                            println!("✅ Block at height {} finalized", certificate.height);
+                            self.broadcast(NetworkMessage::FinalityCertificate(certificate)).await;
+                        }
+                        {
+                            let mut known = self.known_transactions.lock();
+                            for tx in &block.transactions {
+                                known.insert(tx.hash.clone());
+                            }
+                        }
+                        println!(
+                            "📩 Block imported from {}: {} transactions, timestamp: {}",
+                            peer_id,
+                            block.transactions.len(),
+                            block.header.timestamp
+                        );
+                        self.broadcast_except(peer_id, NetworkMessage::Block(block)).await;
+                    }
+                    Err(err) => eprintln!("❌ Dropped block from {}: {}", peer_id, err),
+                }
+            }
+            NetworkMessage::Transaction(tx) => {
+                if self.known_transactions.lock().contains(&tx.hash) {
+                    return;
+                }
+
+                let hash = tx.hash.clone();
+                if let Err(err) = verify_transaction(&tx) {
+                    eprintln!("❌ Invalid transaction {} from {}: {}", hash, peer_id, err);
+                    self.penalize(peer_id, Misbehaviour::InvalidTransaction).await;
+                    return;
+                }
+
+                // Anything else (duplicates, used nonces, a full pool, funds
+                // already committed to pending transactions) can be an honest
+                // race, so it is dropped without penalty.
+                let mut consensus_engine = self.consensus_engine.clone();
+                match add_transaction(&mut consensus_engine, tx) {
+                    Ok(()) => {
+                        self.known_transactions.lock().insert(hash.clone());
+                        println!("📩 Transaction {} accepted from {}", hash, peer_id);
+                        self.broadcast_except(peer_id, NetworkMessage::AnnounceTransactions(vec![hash])).await;
+                    }
+                    Err(err) => eprintln!("❌ Rejected transaction {} from {}: {}", hash, peer_id, err),
+                }
+            }
+            NetworkMessage::AnnounceTransactions(hashes) => {
+                let unknown: Vec<String> = {
+                    let known = self.known_transactions.lock();
+                    hashes.into_iter().filter(|hash| !known.contains(hash)).collect()
+                };
+                if !unknown.is_empty() {
+                    let _ = reply.send(NetworkMessage::GetTransactions(unknown));
+                }
+            }
+            NetworkMessage::GetTransactions(hashes) => {
+                let transactions: Vec<Transaction> = {
+                    let mempool = self.consensus_engine.mempool.lock();
+                    hashes.iter().filter_map(|hash| mempool.get(hash).cloned()).collect()
+                };
+                for tx in transactions {
+                    let _ = reply.send(NetworkMessage::Transaction(tx));
+                }
+            }
+            NetworkMessage::GetHeaders { start_height, limit } => {
+                let limit = limit.min(MAX_HEADERS_PER_REQUEST) as usize;
+                let headers = {
+                    let chain = self.consensus_engine.chain.lock();
+                    chain
+                        .iter()
+                        .skip(start_height as usize)
+                        .take(limit)
+                        .map(|block| block.header.clone())
+                        .collect()
+                };
+                let _ = reply.send(NetworkMessage::Headers(headers));
+            }
+            NetworkMessage::GetStatus => {
+                let (best_height, best_hash) = {
+                    let chain = self.consensus_engine.chain.lock();
+                    (
+                        chain.last().map(|block| block.header.height + 1).unwrap_or(0),
+                        chain.last().map(hash_block).unwrap_or([0u8; 32]),
+                    )
+                };
+                let _ = reply.send(NetworkMessage::Status { best_height, best_hash });
+            }
+            NetworkMessage::GetBlocks(heights) => {
+                let blocks = {
+                    let chain = self.consensus_engine.chain.lock();
+                    heights
+                        .iter()
+                        .take(MAX_BLOCKS_PER_REQUEST)
+                        .filter_map(|height| chain.get(*height as usize).cloned())
+                        .collect()
+                };
+                let _ = reply.send(NetworkMessage::Blocks(blocks));
+            }
+            response @ (NetworkMessage::Headers(_) | NetworkMessage::Status { .. } | NetworkMessage::Blocks(_)) => {
+                let pending = self.pending_requests.lock().remove(peer_id);
+                match pending {
+                    Some(waiter) => {
+                        let _ = waiter.send(response);
+                    }
+                    None => eprintln!("❌ Unsolicited response from {}", peer_id),
+                }
+            }
+            NetworkMessage::GetPeers => {
+                let nodes = self.node_registry.get_all_nodes();
+                let _ = reply.send(NetworkMessage::Peers(nodes));
+            }
+            NetworkMessage::Peers(nodes) => {
+                let mut discovered = 0;
+                // Peer records are unauthenticated, so they only ever add
+                // nodes we do not know yet and never replace existing ones.
+                for node in nodes {
+                    let address = format!("{}:{}", node.ip_address, node.port);
+                    if address == self.config.public_address || node.dc_id == self.config.local_node_id || node.port == 0 {
+                        continue;
+                    }
+                    if !self.node_registry.register_new_node(node) {
+                        continue;
+                    }
+                    discovered += 1;
+                    self.address_book.add(address);
+                }
+                if discovered > 0 {
+                    println!("📩 Discovered {} new nodes from {}", discovered, peer_id);
+                }
+            }
+            NetworkMessage::BlockVote(vote) => {
+                let mut consensus_engine = self.consensus_engine.clone();
+                if has_voted(&consensus_engine, &vote.block_hash, &vote.validator)
+                    || finalized_height(&consensus_engine).is_some_and(|finalized| vote.height <= finalized)
+                    || !consensus_engine.tree.lock().contains(&vote.block_hash)
+                {
+                    return;
+                }
+
+                match add_vote(&mut consensus_engine, vote.clone()) {
+                    Ok(certificate) => {
+                        self.broadcast_except(peer_id, NetworkMessage::BlockVote(vote)).await;
+                        if let Some(certificate) = certificate {
+                            println!("✅ Block at height {} finalized", certificate.height);
+                            self.broadcast(NetworkMessage::FinalityCertificate(certificate)).await;
+                        }
+                    }
+                    Err(err) => {
+                        eprintln!("❌ Rejected vote from {}: {}", peer_id, err);
+                        self.penalize(peer_id, Misbehaviour::InvalidVote).await;
+                    }
+                }
+            }
+            NetworkMessage::FinalityCertificate(certificate) => {
+                let mut consensus_engine = self.consensus_engine.clone();
+                let height = certificate.height;
+                match import_certificate(&mut consensus_engine, certificate.clone()) {
+                    Ok(true) => {
+                        println!("✅ Block at height {} finalized by certificate from {}", height, peer_id);
+                        self.broadcast_except(peer_id, NetworkMessage::FinalityCertificate(certificate)).await;
+                    }
+                    Ok(false) => {}
+                    Err(err) => eprintln!("❌ Ignored finality certificate from {}: {}", peer_id, err),
+                }
+            }
+            NetworkMessage::Evidence(evidence) => {
+                let mut consensus_engine = self.consensus_engine.clone();
+                match add_evidence(&mut consensus_engine, (*evidence).clone()) {
+                    Ok(true) => self.broadcast_except(peer_id, NetworkMessage::Evidence(evidence)).await,
+                    Ok(false) => {}
+                    Err(err) => {
+                        eprintln!("❌ Rejected evidence from {}: {}", peer_id, err);
+                        self.penalize(peer_id, Misbehaviour::InvalidEvidence).await;
+                    }
+                }
+            }
+            NetworkMessage::Unjail(unjail) => {
+                let mut consensus_engine = self.consensus_engine.clone();
+                match add_unjail(&mut consensus_engine, unjail.clone()) {
+                    Ok(true) => self.broadcast_except(peer_id, NetworkMessage::Unjail(unjail)).await,
+                    Ok(false) => {}
+                    Err(err) => {
+                        eprintln!("❌ Rejected unjail request from {}: {}", peer_id, err);
+                        self.penalize(peer_id, Misbehaviour::InvalidUnjail).await;
+                    }
+                }
+            }
+            NetworkMessage::XNetSlice(slice) => {
+                self.handle_xnet_slice(peer_id, slice).await;
+            }
+            NetworkMessage::XNetAck(ack) => {
+                self.handle_xnet_ack(peer_id, ack).await;
+            }
+        }
+    }
+
+    pub fn seed_address_book(&self, bootstrap_nodes: Vec<String>) {
+        for address in bootstrap_nodes {
+            if address != self.config.public_address {
+                self.address_book.add(address);
+            }
+        }
+
+        for node in self.node_registry.get_all_nodes() {
+            let address = format!("{}:{}", node.ip_address, node.port);
+            if node.port != 0 && address != self.config.public_address {
+                self.address_book.add(address);
+            }
+        }
+    }
+
+    /// Keeps dialing known addresses until `target_peers` connections are open.
+    /// Failed addresses are retried with exponential backoff by the address book.
+    pub async fn maintain_peers(&self) {
+        loop {
+            let connected: HashSet<String> = {
+                let peers_map = self.peers.lock().await;
+                peers_map.values().map(|peer| peer.dial_address.clone()).collect()
+            };
+
+            if connected.len() < self.config.target_peers {
+                let missing = self.config.target_peers - connected.len();
+                let candidates: Vec<String> = self
+                    .address_book
+                    .dial_candidates()
+                    .into_iter()
+                    .filter(|address| !connected.contains(address) && *address != self.config.public_address)
+                    .filter(|address| {
+                        address
+                            .parse::<SocketAddr>()
+                            .map(|addr| !self.reputation.is_banned(&addr.ip().to_string()))
+                            .unwrap_or(true)
+                    })
+                    .take(missing)
+                    .collect();
+
+                for address in candidates {
+                    if let Err(e) = self.connect(&address).await {
+                        eprintln!("❌ Failed to connect to peer {}: {}", address, e);
+                    }
+                }
+
+                if !connected.is_empty() {
+                    self.broadcast(NetworkMessage::GetPeers).await;
+                }
+            }
+
+            sleep(PEER_MAINTENANCE_INTERVAL).await;
+        }
+    }
+
+    /// Admits a locally submitted transaction into the mempool and announces it to every peer.
+    pub async fn submit_transaction(&self, tx: Transaction) -> Result<(), String> {
+        let hash = tx.hash.clone();
+        let mut consensus_engine = self.consensus_engine.clone();
+        add_transaction(&mut consensus_engine, tx)?;
+
+        self.known_transactions.lock().insert(hash.clone());
+        self.broadcast(NetworkMessage::AnnounceTransactions(vec![hash])).await;
+        Ok(())
+    }
+
+    /// Queues a locally signed unjail request and gossips it to the subnet.
+    pub async fn submit_unjail(&self, unjail: Unjail) -> Result<(), String> {
+        let mut consensus_engine = self.consensus_engine.clone();
+        add_unjail(&mut consensus_engine, unjail.clone())?;
+
+        self.broadcast(NetworkMessage::Unjail(unjail)).await;
+        Ok(())
+    }
+
+    fn mempool_hashes(&self) -> Vec<String> {
+        self.consensus_engine.mempool.lock().hashes()
+    }
+
+    /// Sends `message` to `peer_id` and waits for its response. Only one request
+    /// per peer may be outstanding at a time.
+    pub async fn request(&self, peer_id: &str, message: NetworkMessage) -> Result<NetworkMessage, String> {
+        let (waiter, response) = oneshot::channel();
+        {
+            let mut pending = self.pending_requests.lock();
+            if pending.contains_key(peer_id) {
+                return Err(format!("Request to {} already in flight", peer_id));
+            }
+            pending.insert(peer_id.to_string(), waiter);
+        }
+
+        if let Err(err) = self.send(peer_id, message).await {
+            self.pending_requests.lock().remove(peer_id);
+            return Err(err);
+        }
+
+        match timeout(REQUEST_TIMEOUT, response).await {
+            Ok(Ok(message)) => Ok(message),
+            Ok(Err(_)) => Err(format!("Peer {} disconnected before responding", peer_id)),
+            Err(_) => {
+                self.pending_requests.lock().remove(peer_id);
+                Err(format!("Request to {} timed out", peer_id))
+            }
+        }
+    }
+
+    pub async fn penalize(&self, peer_id: &str, misbehaviour: Misbehaviour) {
+        let address = {
+            let peers_map = self.peers.lock().await;
+            peers_map.get(peer_id).map(|peer| peer.address.clone())
+        };
+
+        if let Some(ip) = address.and_then(|address| address.parse::<SocketAddr>().ok()).map(|addr| addr.ip().to_string()) {
+            self.reputation.report(peer_id, &ip, misbehaviour);
+        }
+    }
+
+    pub async fn send(&self, peer: &str, message: NetworkMessage) -> Result<(), String> {
+        let peers_map = self.peers.lock().await;
+        let peer_entry = peers_map.get(peer).ok_or_else(|| format!("Peer {} not connected", peer))?;
+        peer_entry.sender.send(message).map_err(|_| format!("Peer {} connection closed", peer))
+    }
+
+    /// Sends `message` to every peer. Subnet-scoped messages only reach peers
+    /// running consensus for the same subnet as this node.
+    pub async fn broadcast(&self, message: NetworkMessage) {
+        let peers_map = self.peers.lock().await;
+        for peer in peers_map.values() {
+            if self.should_receive(peer, &message) {
+                let _ = peer.sender.send(message.clone());
+            }
+        }
+    }
+
+    pub async fn broadcast_except(&self, excluded: &str, message: NetworkMessage) {
+        let peers_map = self.peers.lock().await;
+        for (peer_id, peer) in peers_map.iter() {
+            if peer_id != excluded && self.should_receive(peer, &message) {
+                let _ = peer.sender.send(message.clone());
+            }
+        }
+    }
+
+    fn should_receive(&self, peer: &Peer, message: &NetworkMessage) -> bool {
+        !message.is_subnet_scoped() || peer.subnet_id == self.consensus_engine.subnet_id
+    }
+}
+
+fn handshake_signing_payload(nonce: &[u8; 32], node_id: &str, chain_id: &str) -> Vec<u8> {
+    let mut payload = b"nebula-handshake".to_vec();
+    payload.extend_from_slice(nonce);
+    payload.extend_from_slice(node_id.as_bytes());
+    payload.extend_from_slice(chain_id.as_bytes());
+    payload
+}
+
+fn parse_node_id(node_id: &str) -> Result<VerifyingKey, String> {
+    let bytes = hex::decode(node_id).map_err(|e| format!("Invalid node id: {}", e))?;
+    let array: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| "Invalid node id length: Expected 32 bytes".to_string())?;
+    VerifyingKey::from_bytes(&array).map_err(|e| format!("Invalid node id key: {}", e))
+}
+
+fn handshake_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, message)
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use chrono::Utc;
+use rocksdb::DB;
+use serde::{Deserialize, Serialize};
+use crate::core::types::MutexWrapper;
+
+pub const BAN_THRESHOLD: i32 = -100;
+/// Points a peer's score recovers per minute without misbehaving, so honest
+/// peers that trip over an occasional race are never banned for it.
+pub const SCORE_RECOVERY_PER_MINUTE: i32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehaviour {
+    InvalidBlock,
+    InvalidTransaction,
+    MalformedMessage,
+    RateLimited,
+    InvalidStreamSlice,
+    InvalidVote,
+    InvalidEvidence,
+    InvalidUnjail,
+}
+
+impl Misbehaviour {
+    pub fn penalty(&self) -> i32 {
+        match self {
+            Misbehaviour::InvalidBlock => 50,
+            Misbehaviour::InvalidTransaction => 10,
+            Misbehaviour::MalformedMessage => 25,
+            Misbehaviour::RateLimited => 5,
+            Misbehaviour::InvalidStreamSlice => 50,
+            Misbehaviour::InvalidVote => 25,
+            Misbehaviour::InvalidEvidence => 50,
+            Misbehaviour::InvalidUnjail => 25,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Ban {
+    pub ip: String,
+    pub node_id: String,
+    pub reason: String,
+    pub banned_until: i64,
+}
+
+/// Token bucket limiting how many messages a single peer may send per second.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(messages_per_second: u32) -> Self {
+        let refill_per_sec = messages_per_second as f64;
+        Self {
+            capacity: refill_per_sec * 2.0,
+            refill_per_sec,
+            tokens: refill_per_sec * 2.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    pub fn allow(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PeerScore {
+    pub score: i32,
+    pub updated_at: i64,
+}
+
+impl PeerScore {
+    /// Score at `now` after recovering towards zero since the last report.
+    pub fn decayed(&self, now: i64) -> i32 {
+        let minutes = ((now - self.updated_at).max(0) / 60).min(i32::MAX as i64) as i32;
+        self.score.saturating_add(minutes.saturating_mul(SCORE_RECOVERY_PER_MINUTE)).min(0)
+    }
+}
+
+#[derive(Clone)]
+pub struct PeerReputation {
+    pub scores: Arc<MutexWrapper<HashMap<String, PeerScore>>>,
+    pub bans: Arc<MutexWrapper<HashMap<String, Ban>>>,
+    pub ban_duration_secs: i64,
+    db: Arc<DB>,
+}
+
+impl PeerReputation {
+    pub fn new(db: Arc<DB>, ban_duration_secs: i64) -> Self {
+        let reputation = Self {
+            scores: Arc::new(MutexWrapper::new(HashMap::new())),
+            bans: Arc::new(MutexWrapper::new(HashMap::new())),
+            ban_duration_secs,
+            db,
+        };
+        reputation.load_state();
+        reputation
+    }
+
+    /// Lowers the score of `node_id` and bans `ip` once the score crosses
+    /// `BAN_THRESHOLD`. Scores recover over time. Returns `true` if the peer
+    /// is now banned.
+    pub fn report(&self, node_id: &str, ip: &str, misbehaviour: Misbehaviour) -> bool {
+        let now = Utc::now().timestamp();
+        let score = {
+            let mut scores = self.scores.lock();
+            let entry = scores.entry(node_id.to_string()).or_insert(PeerScore { score: 0, updated_at: now });
+            entry.score = entry.decayed(now) - misbehaviour.penalty();
+            entry.updated_at = now;
+            entry.score
+        };
+
+        if score > BAN_THRESHOLD {
+            return false;
+        }
+
+        self.ban(ip, node_id, format!("Score {} after {:?}", score, misbehaviour));
+        self.scores.lock().remove(node_id);
+        true
+    }
+
+    pub fn ban(&self, ip: &str, node_id: &str, reason: String) {
+        let ban = Ban {
+            ip: ip.to_string(),
+            node_id: node_id.to_string(),
+            reason,
+            banned_until: Utc::now().timestamp() + self.ban_duration_secs,
+        };
+
+        let serialized = bincode::serialize(&ban).unwrap();
+        let key = format!("ban_{}", ip);
+        self.db.put(key.as_bytes(), serialized).unwrap();
+
+        println!("🚫 Banned {} ({}) until {}: {}", ip, node_id, ban.banned_until, ban.reason);
+        self.bans.lock().insert(ip.to_string(), ban);
+    }
+
+    pub fn is_banned(&self, ip: &str) -> bool {
+        let expired = {
+            let bans = self.bans.lock();
+            match bans.get(ip) {
+                Some(ban) => ban.banned_until <= Utc::now().timestamp(),
+                None => return false,
+            }
+        };
+
+        if expired {
+            self.clear_ban(ip);
+            return false;
+        }
+        true
+    }
+
+    pub fn list_bans(&self) -> Vec<Ban> {
+        let now = Utc::now().timestamp();
+        let bans = self.bans.lock();
+        bans.values().filter(|ban| ban.banned_until > now).cloned().collect()
+    }
+
+    pub fn clear_ban(&self, ip: &str) -> bool {
+        let key = format!("ban_{}", ip);
+        self.db.delete(key.as_bytes()).unwrap();
+        self.bans.lock().remove(ip).is_some()
+    }
+
+    pub fn load_state(&self) {
+        let mut bans = self.bans.lock();
+        bans.clear();
+        let iter = self.db.iterator(rocksdb::IteratorMode::Start);
+        for item in iter {
+            let (key, value) = item.unwrap();
+            if key.starts_with(b"ban_")
+                && let Ok(ban) = bincode::deserialize::<Ban>(&value)
+            {
+                bans.insert(ban.ip.clone(), ban);
+            }
+        }
+    }
+}
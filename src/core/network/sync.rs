@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, Duration, Instant};
+
+use crate::core::consensus::{finalized_height, hash_block, hash_header, import_block, verify_block_stateless};
+use crate::core::network::message::NetworkMessage;
+use crate::core::network::network::Network;
+use crate::core::network::reputation::Misbehaviour;
+use crate::core::types::{Block, BlockHeader};
+
+const HEADERS_PER_REQUEST: u32 = 512;
+const BLOCKS_PER_REQUEST: usize = 64;
+const SYNC_INTERVAL: Duration = Duration::from_secs(10);
+const STARTUP_PEER_WAIT: Duration = Duration::from_secs(15);
+
+impl Network {
+    pub fn is_synced(&self) -> bool {
+        self.synced.load(Ordering::SeqCst)
+    }
+
+    /// Catches the local chain up with peers, then keeps checking for peers that
+    /// pulled ahead. Consensus participation is paused while the node is behind.
+    pub async fn run_sync_loop(&self) {
+        let started = Instant::now();
+        while self.peers.lock().await.is_empty() && started.elapsed() < STARTUP_PEER_WAIT {
+            sleep(Duration::from_millis(500)).await;
+        }
+
+        loop {
+            match self.sync_chain().await {
+                Ok(()) => {
+                    if !self.synced.swap(true, Ordering::SeqCst) {
+                        println!("✅ Chain synchronized at height {}", self.local_height());
+                    }
+                }
+                Err(err) => eprintln!("❌ Chain sync error: {}", err),
+            }
+
+            sleep(SYNC_INTERVAL).await;
+        }
+    }
+
+    /// Downloads headers from the best peer, fetches the bodies in parallel from
+    /// every peer that has them and applies each block through `import_block`.
+    pub async fn sync_chain(&self) -> Result<(), String> {
+        loop {
+            let local_height = self.local_height();
+            let peer_heights = self.peer_heights().await;
+
+            let Some((best_peer, best_height)) = peer_heights
+                .iter()
+                .max_by_key(|(_, height)| *height)
+                .map(|(peer, height)| (peer.clone(), *height))
+            else {
+                return Ok(());
+            };
+
+            if best_height <= local_height {
+                return Ok(());
+            }
+
+            if self.synced.swap(false, Ordering::SeqCst) {
+                println!("⚡ Behind peer {} ({} > {}), syncing...", best_peer, best_height, local_height);
+            }
+
+            // If the peer is on another branch, fetch its chain from just above
+            // our finalized block; fork choice decides which branch wins.
+            let (start_height, headers) = match self.download_headers(&best_peer, local_height).await {
+                Ok(headers) => (local_height, headers),
+                Err(err) => {
+                    let fork_point = finalized_height(&self.consensus_engine).map(|height| height + 1).unwrap_or(0);
+                    if fork_point >= local_height {
+                        return Err(err);
+                    }
+                    (fork_point, self.download_headers(&best_peer, fork_point).await?)
+                }
+            };
+            if headers.is_empty() {
+                return Err(format!("Peer {} advertised height {} but sent no headers", best_peer, best_height));
+            }
+
+            let last_height = start_height + headers.len() as u64;
+            let sources: Vec<String> = peer_heights
+                .into_iter()
+                .filter(|(_, height)| *height >= last_height)
+                .map(|(peer, _)| peer)
+                .collect();
+
+            let blocks = self.download_blocks(&sources, start_height, &headers).await?;
+            self.apply_blocks(blocks).await?;
+        }
+    }
+
+    /// Height the next block on our chain will have.
+    fn local_height(&self) -> u64 {
+        let chain = self.consensus_engine.chain.lock();
+        chain.last().map(|block| block.header.height + 1).unwrap_or(0)
+    }
+
+    async fn peer_heights(&self) -> Vec<(String, u64)> {
+        let peer_ids: Vec<String> = {
+            let peers_map = self.peers.lock().await;
+            peers_map
+                .values()
+                .filter(|peer| peer.subnet_id == self.consensus_engine.subnet_id)
+                .map(|peer| peer.node_id.clone())
+                .collect()
+        };
+
+        let mut requests = JoinSet::new();
+        for peer_id in peer_ids {
+            let network = self.clone();
+            requests.spawn(async move {
+                let response = network.request(&peer_id, NetworkMessage::GetStatus).await;
+                (peer_id, response)
+            });
+        }
+
+        let mut heights = Vec::new();
+        while let Some(joined) = requests.join_next().await {
+            if let Ok((peer_id, Ok(NetworkMessage::Status { best_height, .. }))) = joined {
+                heights.push((peer_id, best_height));
+            }
+        }
+        heights
+    }
+
+    async fn download_headers(&self, peer_id: &str, start_height: u64) -> Result<Vec<BlockHeader>, String> {
+        let response = self
+            .request(peer_id, NetworkMessage::GetHeaders { start_height, limit: HEADERS_PER_REQUEST })
+            .await?;
+
+        let NetworkMessage::Headers(headers) = response else {
+            return Err(format!("Peer {} answered GetHeaders with an unexpected message", peer_id));
+        };
+
+        let expected_parent = {
+            let chain = self.consensus_engine.chain.lock();
+            match start_height.checked_sub(1) {
+                Some(parent_height) => chain.get(parent_height as usize).map(hash_block),
+                None => Some([0u8; 32]),
+            }
+        };
+        if headers.first().is_some_and(|header| Some(header.parent_hash) != expected_parent) {
+            return Err(format!("Headers from {} do not extend our chain at height {}", peer_id, start_height));
+        }
+
+        for pair in headers.windows(2) {
+            if pair[1].parent_hash != hash_header(&pair[0]) {
+                self.penalize(peer_id, Misbehaviour::InvalidBlock).await;
+                return Err(format!("Peer {} sent a header chain that does not link", peer_id));
+            }
+        }
+
+        Ok(headers)
+    }
+
+    async fn download_blocks(
+        &self,
+        sources: &[String],
+        start_height: u64,
+        headers: &[BlockHeader],
+    ) -> Result<Vec<Block>, String> {
+        if sources.is_empty() {
+            return Err("No peer can serve the requested blocks".to_string());
+        }
+
+        let chunks: Vec<(u64, &[BlockHeader])> = headers
+            .chunks(BLOCKS_PER_REQUEST)
+            .enumerate()
+            .map(|(index, chunk)| (start_height + (index * BLOCKS_PER_REQUEST) as u64, chunk))
+            .collect();
+
+        let mut fetched: HashMap<[u8; 32], Block> = HashMap::new();
+        for wave in chunks.chunks(sources.len()) {
+            let mut requests = JoinSet::new();
+            for ((first, chunk), peer_id) in wave.iter().zip(sources.iter()) {
+                let heights: Vec<u64> = (*first..*first + chunk.len() as u64).collect();
+                let expected: Vec<[u8; 32]> = chunk.iter().map(hash_header).collect();
+                let peer_id = peer_id.clone();
+                let network = self.clone();
+
+                requests.spawn(async move {
+                    let response = network.request(&peer_id, NetworkMessage::GetBlocks(heights)).await;
+                    (peer_id, expected, response)
+                });
+            }
+
+            while let Some(joined) = requests.join_next().await {
+                let (peer_id, expected, response) = joined.map_err(|e| format!("Block download task failed: {}", e))?;
+
+                let NetworkMessage::Blocks(blocks) = response? else {
+                    return Err(format!("Peer {} answered GetBlocks with an unexpected message", peer_id));
+                };
+
+                let received: Vec<[u8; 32]> = blocks.iter().map(hash_block).collect();
+                if received != expected {
+                    self.penalize(&peer_id, Misbehaviour::InvalidBlock).await;
+                    return Err(format!("Peer {} sent blocks that do not match the downloaded headers", peer_id));
+                }
+
+                if let Some(err) = blocks.iter().find_map(|block| verify_block_stateless(&self.consensus_engine, block).err()) {
+                    self.penalize(&peer_id, Misbehaviour::InvalidBlock).await;
+                    return Err(format!("Peer {} sent an invalid block: {}", peer_id, err));
+                }
+
+                for block in blocks {
+                    fetched.insert(hash_block(&block), block);
+                }
+            }
+        }
+
+        headers
+            .iter()
+            .map(|header| fetched.remove(&hash_header(header)).ok_or_else(|| "Missing block body".to_string()))
+            .collect()
+    }
+
+    async fn apply_blocks(&self, blocks: Vec<Block>) -> Result<(), String> {
+        let mut consensus_engine = self.consensus_engine.clone();
+
+        for block in blocks {
+            let block_hash = hash_block(&block);
+            if consensus_engine.tree.lock().contains(&block_hash) {
+                continue;
+            }
+
+            import_block(&mut consensus_engine, block.clone())?;
+
+            let mut known = self.known_transactions.lock();
+            for tx in &block.transactions {
+                known.insert(tx.hash.clone());
+            }
+        }
+
+        Ok(())
+    }
+}
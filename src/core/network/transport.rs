@@ -0,0 +1,209 @@
+use std::sync::Arc;
+use snow::{Builder, HandshakeState, StatelessTransportState};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+
+use crate::core::network::identity::NodeIdentity;
+use crate::core::network::message::{decode_message, encode_message, read_message, write_message, NetworkMessage, MAX_FRAME_SIZE};
+
+pub const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+
+const TRANSPORT_PLAIN: u8 = 0;
+const TRANSPORT_NOISE: u8 = 1;
+const NOISE_MAX_MESSAGE: usize = 65535;
+const NOISE_TAG_LEN: usize = 16;
+const NOISE_MAX_PLAINTEXT: usize = NOISE_MAX_MESSAGE - NOISE_TAG_LEN;
+
+/// Read side of a peer connection. Encrypted frames are split into Noise
+/// transport messages of at most 64 KiB each.
+pub enum FrameReader {
+    Plain(OwnedReadHalf),
+    Noise {
+        reader: OwnedReadHalf,
+        state: Arc<StatelessTransportState>,
+        nonce: u64,
+    },
+}
+
+pub enum FrameWriter {
+    Plain(OwnedWriteHalf),
+    Noise {
+        writer: OwnedWriteHalf,
+        state: Arc<StatelessTransportState>,
+        nonce: u64,
+    },
+}
+
+pub struct Transport {
+    pub reader: FrameReader,
+    pub writer: FrameWriter,
+    /// X25519 static key the peer proved during the Noise handshake, if encrypted.
+    pub remote_static: Option<[u8; 32]>,
+}
+
+impl FrameReader {
+    pub async fn read_message(&mut self) -> io::Result<Option<NetworkMessage>> {
+        match self {
+            FrameReader::Plain(reader) => read_message(reader).await,
+            FrameReader::Noise { reader, state, nonce } => {
+                let len = match reader.read_u32().await {
+                    Ok(len) => len as usize,
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                    Err(e) => return Err(e),
+                };
+
+                if len > MAX_FRAME_SIZE {
+                    return Err(invalid_data(format!("Frame of {} bytes exceeds maximum of {} bytes", len, MAX_FRAME_SIZE)));
+                }
+
+                let mut payload = Vec::with_capacity(len);
+                let mut ciphertext = vec![0u8; NOISE_MAX_MESSAGE];
+                let mut plaintext = vec![0u8; NOISE_MAX_MESSAGE];
+                while payload.len() < len {
+                    let chunk_len = reader.read_u16().await? as usize;
+                    reader.read_exact(&mut ciphertext[..chunk_len]).await?;
+
+                    let n = state
+                        .read_message(*nonce, &ciphertext[..chunk_len], &mut plaintext)
+                        .map_err(|e| invalid_data(format!("Noise decryption failed: {}", e)))?;
+                    *nonce += 1;
+
+                    payload.extend_from_slice(&plaintext[..n]);
+                }
+
+                if payload.len() != len {
+                    return Err(invalid_data("Encrypted frame length mismatch".to_string()));
+                }
+
+                decode_message(&payload).map(Some).map_err(invalid_data)
+            }
+        }
+    }
+}
+
+impl FrameWriter {
+    pub async fn write_message(&mut self, message: &NetworkMessage) -> io::Result<()> {
+        match self {
+            FrameWriter::Plain(writer) => write_message(writer, message).await,
+            FrameWriter::Noise { writer, state, nonce } => {
+                let payload = encode_message(message).map_err(invalid_data)?;
+
+                let mut frame = Vec::with_capacity(payload.len() + 4 + (payload.len() / NOISE_MAX_PLAINTEXT + 1) * (NOISE_TAG_LEN + 2));
+                frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+
+                let mut ciphertext = vec![0u8; NOISE_MAX_MESSAGE];
+                for chunk in payload.chunks(NOISE_MAX_PLAINTEXT) {
+                    let n = state
+                        .write_message(*nonce, chunk, &mut ciphertext)
+                        .map_err(|e| invalid_data(format!("Noise encryption failed: {}", e)))?;
+                    *nonce += 1;
+
+                    frame.extend_from_slice(&(n as u16).to_be_bytes());
+                    frame.extend_from_slice(&ciphertext[..n]);
+                }
+
+                writer.write_all(&frame).await?;
+                writer.flush().await
+            }
+        }
+    }
+}
+
+/// Sets up the transport for a freshly opened connection. The dialing side always
+/// offers a Noise XX handshake; the accepting side falls back to plaintext only
+/// when `require_encryption` is off.
+pub async fn establish(
+    stream: TcpStream,
+    identity: &NodeIdentity,
+    initiator: bool,
+    require_encryption: bool,
+) -> io::Result<Transport> {
+    let (mut reader, mut writer) = stream.into_split();
+
+    let mode = if initiator {
+        writer.write_u8(TRANSPORT_NOISE).await?;
+        TRANSPORT_NOISE
+    } else {
+        reader.read_u8().await?
+    };
+
+    match mode {
+        TRANSPORT_PLAIN if require_encryption => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "Peer requested a plaintext transport but encryption is required",
+        )),
+        TRANSPORT_PLAIN => Ok(Transport {
+            reader: FrameReader::Plain(reader),
+            writer: FrameWriter::Plain(writer),
+            remote_static: None,
+        }),
+        TRANSPORT_NOISE => {
+            let builder = Builder::new(NOISE_PARAMS.parse().map_err(noise_error)?);
+            let private_key = identity.signing_key.to_scalar_bytes();
+            let builder = builder.local_private_key(&private_key);
+            let mut handshake = if initiator {
+                builder.build_initiator().map_err(noise_error)?
+            } else {
+                builder.build_responder().map_err(noise_error)?
+            };
+
+            run_noise_handshake(&mut handshake, &mut reader, &mut writer).await?;
+
+            let remote_static: [u8; 32] = handshake
+                .get_remote_static()
+                .and_then(|key| key.try_into().ok())
+                .ok_or_else(|| invalid_data("Peer did not provide a Noise static key".to_string()))?;
+            let state = Arc::new(handshake.into_stateless_transport_mode().map_err(noise_error)?);
+
+            Ok(Transport {
+                reader: FrameReader::Noise { reader, state: Arc::clone(&state), nonce: 0 },
+                writer: FrameWriter::Noise { writer, state, nonce: 0 },
+                remote_static: Some(remote_static),
+            })
+        }
+        other => Err(invalid_data(format!("Unknown transport mode {}", other))),
+    }
+}
+
+async fn run_noise_handshake<R, W>(
+    handshake: &mut HandshakeState,
+    reader: &mut R,
+    writer: &mut W,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buffer = vec![0u8; NOISE_MAX_MESSAGE];
+    let mut scratch = vec![0u8; NOISE_MAX_MESSAGE];
+
+    while !handshake.is_handshake_finished() {
+        if handshake.is_my_turn() {
+            let n = handshake.write_message(&[], &mut buffer).map_err(noise_error)?;
+            writer.write_u16(n as u16).await?;
+            writer.write_all(&buffer[..n]).await?;
+            writer.flush().await?;
+        } else {
+            let n = reader.read_u16().await? as usize;
+            reader.read_exact(&mut buffer[..n]).await?;
+            handshake.read_message(&buffer[..n], &mut scratch).map_err(noise_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The X25519 key a node uses for Noise, derived from its ed25519 identity so
+/// that the encrypted channel is bound to the node id proven in `Hello`.
+pub fn noise_public_key(identity_key: &ed25519_dalek::VerifyingKey) -> [u8; 32] {
+    identity_key.to_montgomery().to_bytes()
+}
+
+fn noise_error(error: snow::Error) -> io::Error {
+    invalid_data(format!("Noise handshake failed: {}", error))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,116 @@
+use ed25519_dalek::SigningKey;
+use hex;
+use tokio::time::{sleep, Duration};
+
+use crate::core::network::message::NetworkMessage;
+use crate::core::network::network::Network;
+use crate::core::network::reputation::Misbehaviour;
+use crate::core::xnet::{certify_slice, sign_ack, StreamAck, StreamSlice};
+
+const XNET_INTERVAL: Duration = Duration::from_secs(2);
+
+impl Network {
+    /// Periodically certifies the pending part of every outgoing stream with this
+    /// node's validator key and sends it to the peers of the destination subnet.
+    /// Slices are resent until the destination acknowledges them.
+    pub async fn run_xnet_loop(&self, signing_key: SigningKey) {
+        loop {
+            for dst_subnet in self.xnet.destinations() {
+                let Some(mut slice) = self.xnet.outgoing_slice(&dst_subnet) else {
+                    continue;
+                };
+
+                if let Err(err) = certify_slice(&mut slice, &signing_key) {
+                    eprintln!("❌ Failed to certify XNet slice for {}: {}", dst_subnet, err);
+                    continue;
+                }
+
+                let peers_map = self.peers.lock().await;
+                for peer in peers_map.values().filter(|peer| peer.subnet_id == dst_subnet) {
+                    let _ = peer.sender.send(NetworkMessage::XNetSlice(slice.clone()));
+                }
+            }
+
+            self.send_xnet_acks(&signing_key).await;
+
+            sleep(XNET_INTERVAL).await;
+        }
+    }
+
+    /// Signs how far this node has received every incoming stream and sends
+    /// the ack to the source subnet. Only validators of this subnet ack, since
+    /// the source only counts acks from a quorum of them.
+    async fn send_xnet_acks(&self, signing_key: &SigningKey) {
+        let local_subnet = self.consensus_engine.subnet_id.clone();
+        let my_address = hex::encode(signing_key.verifying_key().to_bytes());
+        if !self.subnet_registry.validators_of(&local_subnet).contains(&my_address) {
+            return;
+        }
+
+        for src_subnet in self.xnet.sources() {
+            let next_index = self.xnet.next_incoming_index(&src_subnet);
+            let ack = match sign_ack(&src_subnet, &local_subnet, next_index, signing_key) {
+                Ok(ack) => ack,
+                Err(err) => {
+                    eprintln!("❌ Failed to sign XNet ack for {}: {}", src_subnet, err);
+                    continue;
+                }
+            };
+
+            let peers_map = self.peers.lock().await;
+            for peer in peers_map.values().filter(|peer| peer.subnet_id == src_subnet) {
+                let _ = peer.sender.send(NetworkMessage::XNetAck(ack.clone()));
+            }
+        }
+    }
+
+    pub(crate) async fn handle_xnet_slice(&self, peer_id: &str, slice: StreamSlice) {
+        if slice.dst_subnet != self.consensus_engine.subnet_id {
+            return;
+        }
+
+        let src_subnet = slice.src_subnet.clone();
+        let validators = self.subnet_registry.validators_of(&src_subnet);
+        if validators.is_empty() {
+            eprintln!("❌ Ignoring XNet slice from {}: no validators known for subnet {}", peer_id, src_subnet);
+            return;
+        }
+
+        match self.xnet.receive_slice(slice, &validators) {
+            Ok(messages) => {
+                if !messages.is_empty() {
+                    println!("📩 {} XNet messages received from subnet {}", messages.len(), src_subnet);
+                    self.xnet.deliver(messages);
+                }
+            }
+            Err(err) => {
+                eprintln!("❌ Rejected XNet slice from {}: {}", peer_id, err);
+                self.penalize(peer_id, Misbehaviour::InvalidStreamSlice).await;
+            }
+        }
+    }
+
+    /// Counts a signed ack for one of our outgoing streams. Acks signed by
+    /// keys outside the destination's validator set are ignored; a bad
+    /// signature from one of its validators is penalized.
+    pub(crate) async fn handle_xnet_ack(&self, peer_id: &str, ack: StreamAck) {
+        if ack.src_subnet != self.consensus_engine.subnet_id {
+            return;
+        }
+
+        let validators = self.subnet_registry.validators_of(&ack.dst_subnet);
+        if !validators.contains(&ack.signature.validator) {
+            return;
+        }
+
+        let dst_subnet = ack.dst_subnet.clone();
+        match self.xnet.receive_ack(ack, &validators) {
+            Ok(Some(next_index)) => println!("📩 XNet stream to {} acknowledged up to {}", dst_subnet, next_index),
+            Ok(None) => {}
+            Err(err) => {
+                eprintln!("❌ Rejected XNet ack from {}: {}", peer_id, err);
+                self.penalize(peer_id, Misbehaviour::InvalidStreamSlice).await;
+            }
+        }
+    }
+}
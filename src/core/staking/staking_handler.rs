@@ -13,9 +13,11 @@ pub fn stake(
     neuron_id: u64,
     amount: u64
 ) -> Result<(), String> {
+    let staker;
     {
         let mut neurons = staking_module.neurons.lock();
         let neuron = neurons.get_mut(&neuron_id).ok_or("Neuron not found")?;
+        staker = neuron.address.clone();
 
         if neuron.private_address != Arc::new(caller.clone()) {
             return Err("Caller is not the owner of this neuron".to_string());
@@ -36,6 +38,7 @@ pub fn stake(
         neuron.staked_amount += amount;
     }
 
+    consensus_engine.mark_local_accounts(std::slice::from_ref(&staker));
     nervous_system.persist_neurons();
     consensus_engine.persist_state();
     Ok(())
@@ -49,9 +52,11 @@ pub fn unstake(
     neuron_id: u64,
     amount: u64
 ) -> Result<(), String> {
+    let staker;
     {
         let mut neurons = staking_module.neurons.lock();
         let neuron = neurons.get_mut(&neuron_id).ok_or("Neuron not found")?;
+        staker = neuron.address.clone();
 
         if neuron.private_address != Arc::new(caller.clone()) {
             return Err("Caller is not the owner of this neuron".to_string());
@@ -77,6 +82,7 @@ pub fn unstake(
         staker_account.balance += amount;
     }
 
+    consensus_engine.mark_local_accounts(std::slice::from_ref(&staker));
     nervous_system.persist_neurons();
     consensus_engine.persist_state();
     Ok(())
@@ -0,0 +1,6 @@
+#[allow(clippy::module_inception)]
+pub mod subnet;
+pub mod registry;
+
+pub use subnet::Subnet;
+pub use registry::SubnetRegistry;
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use rocksdb::DB;
+use crate::core::subnet::subnet::Subnet;
+use crate::core::types::MutexWrapper;
+
+#[derive(Clone)]
+pub struct SubnetRegistry {
+    pub subnets: Arc<MutexWrapper<HashMap<String, Subnet>>>,
+    db: Arc<DB>,
+}
+
+impl SubnetRegistry {
+    pub fn new(db: Arc<DB>) -> Self {
+        let registry = Self {
+            subnets: Arc::new(MutexWrapper::new(HashMap::new())),
+            db,
+        };
+        registry.load_state();
+        registry
+    }
+
+    /// Records `node_id` as a member of `subnet_id`, moving it out of any subnet
+    /// it was previously assigned to.
+    pub fn assign_node(&self, subnet_id: &str, node_id: &str) {
+        let mut subnets = self.subnets.lock();
+
+        for subnet in subnets.values_mut() {
+            if subnet.subnet_id != subnet_id && subnet.node_ids.iter().any(|id| id == node_id) {
+                subnet.node_ids.retain(|id| id != node_id);
+                self.persist_subnet(subnet);
+            }
+        }
+
+        let subnet = subnets.entry(subnet_id.to_string()).or_insert_with(|| Subnet {
+            subnet_id: subnet_id.to_string(),
+            ..Default::default()
+        });
+        if !subnet.node_ids.iter().any(|id| id == node_id) {
+            subnet.node_ids.push(node_id.to_string());
+            self.persist_subnet(subnet);
+        }
+    }
+
+    pub fn assign_canister(&self, subnet_id: &str, canister_id: &str) {
+        let mut subnets = self.subnets.lock();
+        let subnet = subnets.entry(subnet_id.to_string()).or_insert_with(|| Subnet {
+            subnet_id: subnet_id.to_string(),
+            ..Default::default()
+        });
+
+        if !subnet.canister_ids.iter().any(|id| id == canister_id) {
+            subnet.canister_ids.push(canister_id.to_string());
+            self.persist_subnet(subnet);
+        }
+    }
+
+    /// Replaces the validator keys trusted to certify streams sent by `subnet_id`.
+    pub fn set_validators(&self, subnet_id: &str, validators: Vec<String>) {
+        let mut subnets = self.subnets.lock();
+        let subnet = subnets.entry(subnet_id.to_string()).or_insert_with(|| Subnet {
+            subnet_id: subnet_id.to_string(),
+            ..Default::default()
+        });
+
+        if subnet.validators != validators {
+            subnet.validators = validators;
+            self.persist_subnet(subnet);
+        }
+    }
+
+    pub fn validators_of(&self, subnet_id: &str) -> Vec<String> {
+        let subnets = self.subnets.lock();
+        subnets.get(subnet_id).map(|subnet| subnet.validators.clone()).unwrap_or_default()
+    }
+
+    pub fn get_subnet(&self, subnet_id: &str) -> Option<Subnet> {
+        let subnets = self.subnets.lock();
+        subnets.get(subnet_id).cloned()
+    }
+
+    pub fn list_subnets(&self) -> Vec<Subnet> {
+        let subnets = self.subnets.lock();
+        subnets.values().cloned().collect()
+    }
+
+    pub fn subnet_of_node(&self, node_id: &str) -> Option<String> {
+        let subnets = self.subnets.lock();
+        subnets
+            .values()
+            .find(|subnet| subnet.node_ids.iter().any(|id| id == node_id))
+            .map(|subnet| subnet.subnet_id.clone())
+    }
+
+    pub fn subnet_of_canister(&self, canister_id: &str) -> Option<String> {
+        let subnets = self.subnets.lock();
+        subnets
+            .values()
+            .find(|subnet| subnet.canister_ids.iter().any(|id| id == canister_id))
+            .map(|subnet| subnet.subnet_id.clone())
+    }
+
+    fn persist_subnet(&self, subnet: &Subnet) {
+        let serialized = bincode::serialize(subnet).unwrap();
+        let key = format!("subnet_{}", subnet.subnet_id);
+        self.db.put(key.as_bytes(), serialized).unwrap();
+    }
+
+    pub fn load_state(&self) {
+        let mut subnets = self.subnets.lock();
+        subnets.clear();
+        let iter = self.db.iterator(rocksdb::IteratorMode::Start);
+        for item in iter {
+            let (key, value) = item.unwrap();
+            if key.starts_with(b"subnet_")
+                && let Ok(subnet) = bincode::deserialize::<Subnet>(&value)
+            {
+                subnets.insert(subnet.subnet_id.clone(), subnet);
+            }
+        }
+    }
+}
@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+/// Membership record for one subnet: the nodes that run its consensus, the
+/// validator keys that certify its XNet streams and the canisters it hosts.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Subnet {
+    pub subnet_id: String,
+    pub node_ids: Vec<String>,
+    pub validators: Vec<String>,
+    pub canister_ids: Vec<String>,
+}
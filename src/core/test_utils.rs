@@ -0,0 +1,173 @@
+//! Fixtures shared by the unit tests: throwaway databases, deterministic keys
+//! and a consensus engine with a fixed genesis state.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use chrono::{NaiveDate, TimeZone, Utc};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use rocksdb::DB;
+use crate::core::api::v1::transaction::{build_transaction, finalize_transaction};
+use crate::core::consensus::block::{BLOCK_VERSION, compute_merkle_root, hash_block, serialize_header_for_signing};
+use crate::core::consensus::epoch::init_genesis_validator_set;
+use crate::core::consensus::evidence::{evidence_hash, pending_evidence};
+use crate::core::consensus::execution::preview_block;
+use crate::core::consensus::liveness::{pending_unjails, unjail_hash};
+use crate::core::consensus::mempool::MempoolConfig;
+use crate::core::consensus::model::{ConsensusConfig, ConsensusEngine};
+use crate::core::consensus::template::build_block_template;
+use crate::core::consensus::validator::{ValidatorInfo, select_leader};
+use crate::core::crypto::sign_data;
+use crate::core::types::{Address, Block, BlockHeader, MutexWrapper, Neuron, NeuronStatus, Transaction, TransactionType};
+
+static NEXT_DB: AtomicUsize = AtomicUsize::new(0);
+
+/// Opens an empty database in a directory of its own under the system temp dir.
+pub fn temp_db(name: &str) -> Arc<DB> {
+    let path = std::env::temp_dir().join(format!(
+        "nebula_test_{}_{}_{}",
+        name,
+        std::process::id(),
+        NEXT_DB.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = std::fs::remove_dir_all(&path);
+    Arc::new(DB::open_default(path).expect("Failed to open test RocksDB"))
+}
+
+pub fn signing_key(seed: u8) -> SigningKey {
+    SigningKey::from_bytes(&[seed; 32])
+}
+
+pub fn address_of(key: &SigningKey) -> Address {
+    hex::encode(VerifyingKey::from(key).to_bytes())
+}
+
+/// Consensus rules with slot 0 long in the past, so any slot a test picks is
+/// open, and `balance` allocated at genesis to each of `keys`.
+pub fn test_config(keys: &[SigningKey], balance: u64) -> ConsensusConfig {
+    ConsensusConfig {
+        min_fee: 1,
+        fee_burn_percent: 0,
+        max_block_transactions: 1000,
+        max_block_bytes: 1024 * 1024,
+        epoch_length: 100,
+        min_self_stake: 100,
+        max_validators: 100,
+        liveness_window: 100,
+        max_missed_slots: 50,
+        unjail_cooldown: 600,
+        genesis_time: 1,
+        slot_duration_ms: 1000,
+        genesis_allocations: keys.iter().map(|key| (address_of(key), balance)).collect::<BTreeMap<_, _>>(),
+    }
+}
+
+/// Neuron owned by `key` that backs its own validator with `stake`.
+pub fn test_neuron(key: &SigningKey, id: u64, stake: u64) -> Neuron {
+    let created = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+    Neuron {
+        private_address: Arc::new(key.clone()),
+        address: address_of(key),
+        name: format!("TestNeuron{}", id),
+        visibility: true,
+        id,
+        state: NeuronStatus::NotDissolving,
+        staked: true,
+        staked_amount: stake,
+        unlock_date: NaiveDate::from_ymd_opt(2025, 1, 1).unwrap(),
+        age: created.date_naive(),
+        voting_power: 0,
+        maturity: 0,
+        bonus_multiplier: 1.0,
+        date_created: created,
+        dissolve_delay_bonus: 0,
+        age_bonus: 0,
+        total_bonus: 0,
+        is_genesis: true,
+        is_known_neuron: false,
+        validator: Some(address_of(key)),
+    }
+}
+
+/// Engine whose active validators are `keys`, each backed by its own neuron
+/// with `stake` and holding `balance` from the genesis allocation. Engines
+/// built from the same keys start from identical state.
+pub fn test_engine(name: &str, keys: &[SigningKey], stake: u64, balance: u64) -> ConsensusEngine {
+    let validators: Vec<ValidatorInfo> = keys
+        .iter()
+        .enumerate()
+        .map(|(id, key)| ValidatorInfo {
+            address: address_of(key),
+            neuron_id: id as u64,
+            active: true,
+            ..Default::default()
+        })
+        .collect();
+    let neurons: HashMap<u64, Neuron> = keys
+        .iter()
+        .enumerate()
+        .map(|(id, key)| (id as u64, test_neuron(key, id as u64, stake)))
+        .collect();
+
+    let mut engine = ConsensusEngine::new(
+        "subnet-test".to_string(),
+        test_config(keys, balance),
+        MempoolConfig::default(),
+        Arc::new(MutexWrapper::new(validators)),
+        Arc::new(MutexWrapper::new(neurons)),
+        temp_db(name),
+    );
+    engine.init_genesis_ledger().unwrap();
+    init_genesis_validator_set(&engine);
+    engine
+}
+
+/// Signed transfer from `from` with the sender's next nonce.
+pub fn transfer(engine: &mut ConsensusEngine, from: &SigningKey, to: &SigningKey, amount: u64) -> Transaction {
+    let mut tx = build_transaction(engine, address_of(from), address_of(to), amount, 0, 0, TransactionType::Transfer);
+    finalize_transaction(&mut tx, from).unwrap();
+    tx
+}
+
+/// Block for `slot` on top of the current tip, signed by that slot's leader
+/// among `keys`, like `produce_block` but without waiting for the slot or
+/// importing the block. Carries the engine's pending transactions, evidence
+/// and unjail requests.
+pub fn build_block(engine: &ConsensusEngine, keys: &[SigningKey], slot: u64) -> Block {
+    let (parent_hash, height) = {
+        let chain_lock = engine.chain.lock();
+        let parent_hash = chain_lock.last().map(hash_block).unwrap_or([0u8; 32]);
+        (parent_hash, chain_lock.last().map(|blk| blk.header.height + 1).unwrap_or(0))
+    };
+    let leader = select_leader(engine, &parent_hash, height, slot).expect("No leader for slot");
+    let key = keys.iter().find(|key| address_of(key) == leader).expect("Leader key not given");
+
+    let transactions = build_block_template(engine).transactions;
+    let evidence = pending_evidence(engine);
+    let unjails = pending_unjails(engine, height);
+    let header = BlockHeader {
+        version: BLOCK_VERSION,
+        subnet_id: engine.subnet_id.clone(),
+        height,
+        parent_hash,
+        slot,
+        merkle_root: compute_merkle_root(&transactions),
+        state_root: [0u8; 32],
+        ledger_root: [0u8; 32],
+        tx_count: transactions.len() as u32,
+        total_fees: 0,
+        evidence_hash: evidence_hash(&evidence),
+        unjail_hash: unjail_hash(&unjails),
+        timestamp: Utc::now().timestamp() as u64,
+        validator: leader,
+        signature: vec![],
+    };
+    let mut block = Block { header, transactions, evidence, unjails };
+
+    let outcome = preview_block(engine, &block);
+    block.header.state_root = outcome.state_root;
+    block.header.ledger_root = outcome.ledger_root;
+    block.header.total_fees = outcome.total_fees;
+    block.header.signature = sign_data(key, &serialize_header_for_signing(&block.header).unwrap());
+    block
+}
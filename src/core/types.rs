@@ -6,6 +6,7 @@ use parking_lot::Mutex;
 use ed25519_dalek::SigningKey;
 use rocksdb::DB;
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use serde::de::DeserializeOwned;
 use serde::de::Error as DeError;
 pub type Address = String;
 
@@ -112,6 +113,9 @@ pub struct Transaction {
     #[serde(rename = "index")]
     pub index: u32,
 
+    #[serde(rename = "nonce")]
+    pub nonce: u64,
+
     #[serde(rename = "timestamp")]
     pub timestamp: chrono::DateTime<chrono::prelude::Utc>,
 
@@ -139,8 +143,18 @@ pub struct Transaction {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BlockHeader {
+    pub version: u32,
+    pub subnet_id: String,
+    pub height: u64,
     pub parent_hash: [u8; 32],
+    pub slot: u64,
     pub merkle_root: [u8; 32],
+    pub state_root: [u8; 32],
+    pub ledger_root: [u8; 32],
+    pub tx_count: u32,
+    pub total_fees: u64,
+    pub evidence_hash: [u8; 32],
+    pub unjail_hash: [u8; 32],
     pub timestamp: u64,
     pub validator: Address,
     pub signature: Vec<u8>,
@@ -150,6 +164,25 @@ pub struct BlockHeader {
 pub struct Block {
     pub header: BlockHeader,
     pub transactions: Vec<Transaction>,
+    pub evidence: Vec<Evidence>,
+    pub unjails: Vec<Unjail>,
+}
+
+/// Proof that a validator signed two different headers at the same height.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Evidence {
+    pub first: BlockHeader,
+    pub second: BlockHeader,
+}
+
+/// Request signed by a jailed validator to be reactivated. It names the height
+/// the validator was jailed at, so it cannot be replayed after a later jailing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Unjail {
+    pub subnet_id: String,
+    pub validator: Address,
+    pub jailed_at: u64,
+    pub signature: Vec<u8>,
 }
 
 #[derive(Debug)]
@@ -171,7 +204,7 @@ impl<T: ?Sized + Serialize> Serialize for MutexWrapper<T> {
     }
 }
 
-impl<'de, T: ?Sized + Deserialize<'de>> Deserialize<'de> for MutexWrapper<T> {
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for MutexWrapper<T> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
@@ -216,4 +249,35 @@ impl DerefMut for DbWrapper {
     fn deref_mut(&mut self) -> &mut Self::Target {
         Arc::get_mut(&mut self.0).expect("Failed to get mutable reference")
     }
-}
\ No newline at end of file
+}
+
+/// Decodes a stored record, falling back to `Legacy`, the layout it had before
+/// a field was added, and rewriting a legacy record in the current layout. A
+/// record that matches neither is moved to `corrupt_<key>` so it no longer
+/// blocks startup, and an error is returned for the caller to report.
+pub fn decode_record<T, Legacy>(db: &DB, key: &[u8], value: &[u8]) -> Result<T, String>
+where
+    T: Serialize + DeserializeOwned + From<Legacy>,
+    Legacy: DeserializeOwned,
+{
+    if let Ok(record) = bincode::deserialize::<T>(value) {
+        return Ok(record);
+    }
+
+    if let Ok(legacy) = bincode::deserialize::<Legacy>(value) {
+        let record = T::from(legacy);
+        let serialized = bincode::serialize(&record).map_err(|e| e.to_string())?;
+        db.put(key, serialized).map_err(|e| e.to_string())?;
+        return Ok(record);
+    }
+
+    let mut quarantine = b"corrupt_".to_vec();
+    quarantine.extend_from_slice(key);
+    db.put(&quarantine, value).map_err(|e| e.to_string())?;
+    db.delete(key).map_err(|e| e.to_string())?;
+    Err(format!(
+        "Corrupt record {} moved to {}",
+        String::from_utf8_lossy(key),
+        String::from_utf8_lossy(&quarantine)
+    ))
+}
@@ -0,0 +1,104 @@
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use hex;
+use crate::core::crypto::{sign_data, verify_data};
+use crate::core::xnet::stream::{StreamAck, StreamSignature, StreamSlice};
+
+/// Number of distinct validator signatures needed to certify a slice: more
+/// than two thirds of the sending subnet's validators.
+pub fn quorum_size(validator_count: usize) -> usize {
+    validator_count * 2 / 3 + 1
+}
+
+pub fn slice_signing_payload(slice: &StreamSlice) -> Result<Vec<u8>, String> {
+    bincode::serialize(&(&slice.src_subnet, &slice.dst_subnet, &slice.messages)).map_err(|e| e.to_string())
+}
+
+/// Adds this validator's signature over the slice contents.
+pub fn certify_slice(slice: &mut StreamSlice, signing_key: &SigningKey) -> Result<(), String> {
+    let validator = hex::encode(signing_key.verifying_key().to_bytes());
+    if slice.signatures.iter().any(|signature| signature.validator == validator) {
+        return Ok(());
+    }
+
+    let payload = slice_signing_payload(slice)?;
+    slice.signatures.push(StreamSignature {
+        validator,
+        signature: sign_data(signing_key, &payload),
+    });
+    Ok(())
+}
+
+pub fn ack_signing_payload(src_subnet: &str, dst_subnet: &str, next_index: u64) -> Result<Vec<u8>, String> {
+    bincode::serialize(&("xnet_ack", src_subnet, dst_subnet, next_index)).map_err(|e| e.to_string())
+}
+
+/// Acknowledgement, signed by this validator, that every message below
+/// `next_index` from `src_subnet` has been received on `dst_subnet`.
+pub fn sign_ack(src_subnet: &str, dst_subnet: &str, next_index: u64, signing_key: &SigningKey) -> Result<StreamAck, String> {
+    let payload = ack_signing_payload(src_subnet, dst_subnet, next_index)?;
+    Ok(StreamAck {
+        src_subnet: src_subnet.to_string(),
+        dst_subnet: dst_subnet.to_string(),
+        next_index,
+        signature: StreamSignature {
+            validator: hex::encode(signing_key.verifying_key().to_bytes()),
+            signature: sign_data(signing_key, &payload),
+        },
+    })
+}
+
+/// Checks that `ack` is signed by one of the destination subnet's `validators`.
+pub fn verify_ack(ack: &StreamAck, validators: &[String]) -> Result<(), String> {
+    if !validators.contains(&ack.signature.validator) {
+        return Err(format!(
+            "Stream ack signed by {} which is not a validator of subnet {}",
+            ack.signature.validator, ack.dst_subnet
+        ));
+    }
+
+    let pubkey = parse_validator_key(&ack.signature.validator)?;
+    let payload = ack_signing_payload(&ack.src_subnet, &ack.dst_subnet, ack.next_index)?;
+    if !verify_data(&pubkey, &payload, &ack.signature.signature) {
+        return Err(format!("Invalid stream ack signature from {}", ack.signature.validator));
+    }
+    Ok(())
+}
+
+fn parse_validator_key(validator: &str) -> Result<VerifyingKey, String> {
+    let pubkey_bytes = hex::decode(validator)
+        .map_err(|e| format!("Invalid hex address: {}", e))?;
+    let pubkey_array: [u8; 32] = pubkey_bytes
+        .try_into()
+        .map_err(|_| "Invalid length: Expected 32 bytes".to_string())?;
+    VerifyingKey::from_bytes(&pubkey_array)
+        .map_err(|e| format!("Failed to create VerifyingKey: {}", e))
+}
+
+/// Checks every signature on `slice` against the sending subnet's validator
+/// set and returns the validators that signed it. Any signature from an
+/// unknown validator or that fails to verify rejects the whole slice.
+pub fn verify_certification(slice: &StreamSlice, validators: &[String]) -> Result<Vec<String>, String> {
+    let payload = slice_signing_payload(slice)?;
+    let mut signers: Vec<String> = Vec::new();
+
+    for signature in &slice.signatures {
+        if !validators.contains(&signature.validator) {
+            return Err(format!(
+                "Stream slice signed by {} which is not a validator of subnet {}",
+                signature.validator, slice.src_subnet
+            ));
+        }
+
+        let pubkey = parse_validator_key(&signature.validator)?;
+
+        if !verify_data(&pubkey, &payload, &signature.signature) {
+            return Err(format!("Invalid stream slice signature from {}", signature.validator));
+        }
+
+        if !signers.contains(&signature.validator) {
+            signers.push(signature.validator.clone());
+        }
+    }
+
+    Ok(signers)
+}
@@ -0,0 +1,5 @@
+pub mod certification;
+pub mod stream;
+
+pub use certification::{certify_slice, quorum_size, sign_ack, verify_ack, verify_certification};
+pub use stream::{StreamAck, StreamSignature, StreamSlice, VerifiedXNetMessage, XNetMessage, XNetStreams};
@@ -0,0 +1,486 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use rocksdb::DB;
+use serde::{Deserialize, Serialize};
+use crate::core::canister::canister::CanisterFunctionPayload;
+use crate::core::canister::registry::CanisterRegistry;
+use crate::core::consensus::crypto_hash;
+use crate::core::types::MutexWrapper;
+use crate::core::xnet::certification::{quorum_size, slice_signing_payload, verify_ack, verify_certification};
+
+pub const MAX_SLICE_MESSAGES: usize = 256;
+/// Most partially certified slices kept per sending subnet; beyond this the
+/// oldest one from that subnet is evicted.
+const MAX_PENDING_SLICES_PER_SUBNET: usize = 64;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct XNetMessage {
+    pub index: u64,
+    pub sender: String,
+    pub receiver: String,
+    pub method: String,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StreamSignature {
+    pub validator: String,
+    pub signature: Vec<u8>,
+}
+
+/// Contiguous run of messages from one subnet's outgoing stream to another,
+/// together with the sending validators' signatures that certify it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StreamSlice {
+    pub src_subnet: String,
+    pub dst_subnet: String,
+    pub messages: Vec<XNetMessage>,
+    pub signatures: Vec<StreamSignature>,
+}
+
+/// A destination validator's signed statement that it has received every
+/// message of the stream from `src_subnet` below `next_index`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StreamAck {
+    pub src_subnet: String,
+    pub dst_subnet: String,
+    pub next_index: u64,
+    pub signature: StreamSignature,
+}
+
+/// A message taken, in stream order, from a slice whose certification has been
+/// checked. Only `XNetStreams` can build one, so canisters never execute
+/// unchecked cross-subnet traffic.
+#[derive(Clone, Debug)]
+pub struct VerifiedXNetMessage {
+    src_subnet: String,
+    message: XNetMessage,
+}
+
+impl VerifiedXNetMessage {
+    pub fn src_subnet(&self) -> &str {
+        &self.src_subnet
+    }
+
+    pub fn message(&self) -> &XNetMessage {
+        &self.message
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct OutgoingStream {
+    pub next_index: u64,
+    pub messages: Vec<XNetMessage>,
+}
+
+struct PendingSlice {
+    slice: StreamSlice,
+    signers: Vec<String>,
+    /// Arrival order, used to evict the oldest slice first.
+    sequence: u64,
+}
+
+/// Ordered message streams between this node's subnet and every other subnet.
+/// Outgoing messages stay queued until the destination acknowledges them;
+/// incoming slices are only released once certified by a quorum of the
+/// sending subnet's validators and in index order.
+#[derive(Clone)]
+pub struct XNetStreams {
+    pub subnet_id: String,
+    pub outgoing: Arc<MutexWrapper<HashMap<String, OutgoingStream>>>,
+    pub incoming: Arc<MutexWrapper<HashMap<String, u64>>>,
+    pub canister_registry: CanisterRegistry,
+    pending: Arc<MutexWrapper<HashMap<[u8; 32], PendingSlice>>>,
+    pending_sequence: Arc<MutexWrapper<u64>>,
+    /// Highest index each destination validator has acknowledged, per
+    /// destination subnet.
+    acks: Arc<MutexWrapper<HashMap<String, HashMap<String, u64>>>>,
+    db: Arc<DB>,
+}
+
+impl XNetStreams {
+    pub fn new(subnet_id: String, canister_registry: CanisterRegistry, db: Arc<DB>) -> Self {
+        let streams = Self {
+            subnet_id,
+            outgoing: Arc::new(MutexWrapper::new(HashMap::new())),
+            incoming: Arc::new(MutexWrapper::new(HashMap::new())),
+            canister_registry,
+            pending: Arc::new(MutexWrapper::new(HashMap::new())),
+            pending_sequence: Arc::new(MutexWrapper::new(0)),
+            acks: Arc::new(MutexWrapper::new(HashMap::new())),
+            db,
+        };
+        streams.load_state();
+        streams
+    }
+
+    /// Appends a message to the stream towards `dst_subnet` and returns its index.
+    pub fn enqueue(
+        &self,
+        dst_subnet: &str,
+        sender: String,
+        receiver: String,
+        method: String,
+        payload: Vec<u8>,
+    ) -> Result<u64, String> {
+        if dst_subnet == self.subnet_id {
+            return Err(format!("Canister {} is on the local subnet {}", receiver, dst_subnet));
+        }
+
+        let mut outgoing = self.outgoing.lock();
+        let stream = outgoing.entry(dst_subnet.to_string()).or_default();
+        let index = stream.next_index;
+        stream.messages.push(XNetMessage { index, sender, receiver, method, payload });
+        stream.next_index += 1;
+        self.persist_outgoing(dst_subnet, stream);
+
+        Ok(index)
+    }
+
+    /// Subnets that still have unacknowledged messages queued for them.
+    pub fn destinations(&self) -> Vec<String> {
+        let outgoing = self.outgoing.lock();
+        outgoing
+            .iter()
+            .filter(|(_, stream)| !stream.messages.is_empty())
+            .map(|(subnet_id, _)| subnet_id.clone())
+            .collect()
+    }
+
+    /// Unsigned slice of the oldest unacknowledged messages towards `dst_subnet`.
+    pub fn outgoing_slice(&self, dst_subnet: &str) -> Option<StreamSlice> {
+        let outgoing = self.outgoing.lock();
+        let stream = outgoing.get(dst_subnet)?;
+        if stream.messages.is_empty() {
+            return None;
+        }
+
+        Some(StreamSlice {
+            src_subnet: self.subnet_id.clone(),
+            dst_subnet: dst_subnet.to_string(),
+            messages: stream.messages.iter().take(MAX_SLICE_MESSAGES).cloned().collect(),
+            signatures: Vec::new(),
+        })
+    }
+
+    /// Drops every message below `next_index` from the stream towards
+    /// `dst_subnet`. Returns whether anything was dropped.
+    fn acknowledge(&self, dst_subnet: &str, next_index: u64) -> bool {
+        let mut outgoing = self.outgoing.lock();
+        let Some(stream) = outgoing.get_mut(dst_subnet) else {
+            return false;
+        };
+        let before = stream.messages.len();
+        stream.messages.retain(|message| message.index >= next_index);
+        if stream.messages.len() == before {
+            return false;
+        }
+        self.persist_outgoing(dst_subnet, stream);
+        true
+    }
+
+    /// Source subnets this node has received stream messages from.
+    pub fn sources(&self) -> Vec<String> {
+        self.incoming.lock().keys().cloned().collect()
+    }
+
+    /// Records a signed ack from one of the destination subnet's `validators`.
+    /// Messages are only dropped from the outgoing stream once more than two
+    /// thirds of those validators have acknowledged them. Returns the index
+    /// the stream is now acknowledged up to, if it moved.
+    pub fn receive_ack(&self, ack: StreamAck, validators: &[String]) -> Result<Option<u64>, String> {
+        if ack.src_subnet != self.subnet_id {
+            return Err(format!("Stream ack is for subnet {}, expected {}", ack.src_subnet, self.subnet_id));
+        }
+        if validators.is_empty() {
+            return Err(format!("No validator set known for subnet {}", ack.dst_subnet));
+        }
+        verify_ack(&ack, validators)?;
+
+        let acknowledged = {
+            let mut acks = self.acks.lock();
+            let votes = acks.entry(ack.dst_subnet.clone()).or_default();
+            votes.retain(|validator, _| validators.contains(validator));
+            let vote = votes.entry(ack.signature.validator.clone()).or_insert(0);
+            *vote = (*vote).max(ack.next_index);
+
+            let mut indices: Vec<u64> = votes.values().copied().collect();
+            indices.sort_unstable_by(|a, b| b.cmp(a));
+            indices.get(quorum_size(validators.len()) - 1).copied()
+        };
+
+        let Some(next_index) = acknowledged else {
+            return Ok(None);
+        };
+        Ok(self.acknowledge(&ack.dst_subnet, next_index).then_some(next_index))
+    }
+
+    /// Index of the next message expected from `src_subnet`.
+    pub fn next_incoming_index(&self, src_subnet: &str) -> u64 {
+        let incoming = self.incoming.lock();
+        incoming.get(src_subnet).copied().unwrap_or(0)
+    }
+
+    /// Checks a slice received from another subnet. Signatures for the same
+    /// slice contents are collected across deliveries from different sending
+    /// nodes; once a quorum of `validators` has signed, the messages not yet
+    /// delivered are returned in order.
+    pub fn receive_slice(&self, slice: StreamSlice, validators: &[String]) -> Result<Vec<VerifiedXNetMessage>, String> {
+        if slice.dst_subnet != self.subnet_id {
+            return Err(format!("Stream slice is addressed to subnet {}, expected {}", slice.dst_subnet, self.subnet_id));
+        }
+        if validators.is_empty() {
+            return Err(format!("No validator set known for subnet {}", slice.src_subnet));
+        }
+        if slice.messages.windows(2).any(|pair| pair[1].index != pair[0].index + 1) {
+            return Err("Stream slice messages are not contiguous".into());
+        }
+
+        let signers = verify_certification(&slice, validators)?;
+        if signers.is_empty() {
+            return Err("Stream slice carries no validator signature".into());
+        }
+        let slice_hash = crypto_hash(&slice_signing_payload(&slice)?);
+
+        let certified = {
+            let mut pending = self.pending.lock();
+            if !pending.contains_key(&slice_hash) {
+                self.evict_pending(&mut pending, &slice.src_subnet);
+            }
+
+            let sequence = {
+                let mut counter = self.pending_sequence.lock();
+                *counter += 1;
+                *counter
+            };
+            let entry = pending.entry(slice_hash).or_insert_with(|| PendingSlice {
+                slice: StreamSlice { signatures: Vec::new(), ..slice.clone() },
+                signers: Vec::new(),
+                sequence,
+            });
+            for signature in slice.signatures {
+                if !entry.signers.contains(&signature.validator) && signers.contains(&signature.validator) {
+                    entry.signers.push(signature.validator.clone());
+                    entry.slice.signatures.push(signature);
+                }
+            }
+
+            if entry.signers.len() < quorum_size(validators.len()) {
+                return Ok(Vec::new());
+            }
+            pending.remove(&slice_hash).map(|entry| entry.slice)
+        };
+
+        let Some(certified) = certified else {
+            return Ok(Vec::new());
+        };
+
+        let mut incoming = self.incoming.lock();
+        let expected = incoming.get(&certified.src_subnet).copied().unwrap_or(0);
+        let messages: Vec<XNetMessage> = certified
+            .messages
+            .into_iter()
+            .filter(|message| message.index >= expected)
+            .collect();
+
+        let Some(first) = messages.first() else {
+            return Ok(Vec::new());
+        };
+        if first.index != expected {
+            return Err(format!(
+                "Stream from subnet {} skipped ahead to index {}, expected {}",
+                certified.src_subnet, first.index, expected
+            ));
+        }
+
+        let next_index = expected + messages.len() as u64;
+        incoming.insert(certified.src_subnet.clone(), next_index);
+        self.persist_incoming(&certified.src_subnet, next_index);
+
+        Ok(messages
+            .into_iter()
+            .map(|message| VerifiedXNetMessage { src_subnet: certified.src_subnet.clone(), message })
+            .collect())
+    }
+
+    /// Makes room for one more pending slice from `src_subnet` by dropping its
+    /// oldest pending slice, so one subnet cannot evict another's signatures.
+    fn evict_pending(&self, pending: &mut HashMap<[u8; 32], PendingSlice>, src_subnet: &str) {
+        let from_subnet: Vec<(&[u8; 32], u64)> = pending
+            .iter()
+            .filter(|(_, entry)| entry.slice.src_subnet == src_subnet)
+            .map(|(hash, entry)| (hash, entry.sequence))
+            .collect();
+        if from_subnet.len() < MAX_PENDING_SLICES_PER_SUBNET {
+            return;
+        }
+        if let Some(oldest) = from_subnet.into_iter().min_by_key(|(_, sequence)| *sequence).map(|(hash, _)| *hash) {
+            pending.remove(&oldest);
+        }
+    }
+
+    /// Hands verified messages to their receiving canisters on this subnet.
+    pub fn deliver(&self, messages: Vec<VerifiedXNetMessage>) {
+        for message in messages {
+            let receiver = message.message().receiver.clone();
+            let Some(canister) = self.canister_registry.canister_handle(&receiver) else {
+                eprintln!("❌ XNet message for unknown canister {} from subnet {}", receiver, message.src_subnet());
+                continue;
+            };
+
+            let mut canister = canister.lock();
+            match canister.execute_function(CanisterFunctionPayload::XNetCall { message }) {
+                Ok(result) => println!("📩 {}", result),
+                Err(err) => eprintln!("❌ XNet delivery to {} failed: {}", receiver, err),
+            }
+        }
+    }
+
+    fn persist_outgoing(&self, dst_subnet: &str, stream: &OutgoingStream) {
+        let serialized = bincode::serialize(stream).unwrap();
+        let key = format!("xnet_out_{}", dst_subnet);
+        self.db.put(key.as_bytes(), serialized).unwrap();
+    }
+
+    fn persist_incoming(&self, src_subnet: &str, next_index: u64) {
+        let serialized = bincode::serialize(&next_index).unwrap();
+        let key = format!("xnet_in_{}", src_subnet);
+        self.db.put(key.as_bytes(), serialized).unwrap();
+    }
+
+    pub fn load_state(&self) {
+        let mut outgoing = self.outgoing.lock();
+        let mut incoming = self.incoming.lock();
+        outgoing.clear();
+        incoming.clear();
+
+        let iter = self.db.iterator(rocksdb::IteratorMode::Start);
+        for item in iter {
+            let (key, value) = item.unwrap();
+            if let Some(subnet_id) = key.strip_prefix(b"xnet_out_")
+                && let Ok(stream) = bincode::deserialize::<OutgoingStream>(&value)
+            {
+                outgoing.insert(String::from_utf8_lossy(subnet_id).to_string(), stream);
+            } else if let Some(subnet_id) = key.strip_prefix(b"xnet_in_")
+                && let Ok(next_index) = bincode::deserialize::<u64>(&value)
+            {
+                incoming.insert(String::from_utf8_lossy(subnet_id).to_string(), next_index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+    use crate::core::subnet::registry::SubnetRegistry;
+    use crate::core::test_utils::{address_of, signing_key, temp_db};
+    use crate::core::xnet::certification::{certify_slice, sign_ack};
+
+    fn streams(subnet_id: &str) -> XNetStreams {
+        let db = temp_db("xnet");
+        let registry = CanisterRegistry::new(subnet_id.to_string(), SubnetRegistry::new(Arc::clone(&db)), Arc::clone(&db));
+        XNetStreams::new(subnet_id.to_string(), registry, db)
+    }
+
+    fn validators() -> (Vec<SigningKey>, Vec<String>) {
+        let keys: Vec<SigningKey> = (1..=4).map(signing_key).collect();
+        let addresses = keys.iter().map(address_of).collect();
+        (keys, addresses)
+    }
+
+    fn queued(streams: &XNetStreams, dst_subnet: &str) -> Vec<u64> {
+        let outgoing = streams.outgoing.lock();
+        outgoing[dst_subnet].messages.iter().map(|message| message.index).collect()
+    }
+
+    #[test]
+    fn messages_drop_once_a_quorum_acknowledges_them() {
+        let local = streams("subnet-a");
+        let (keys, validators) = validators();
+        for _ in 0..5 {
+            local.enqueue("subnet-b", "sender".into(), "receiver".into(), "call".into(), vec![]).unwrap();
+        }
+        let ack = |key: &SigningKey, next_index: u64| sign_ack("subnet-a", "subnet-b", next_index, key).unwrap();
+
+        assert_eq!(local.receive_ack(ack(&keys[0], 5), &validators).unwrap(), None);
+        assert_eq!(local.receive_ack(ack(&keys[1], 3), &validators).unwrap(), None);
+        assert_eq!(queued(&local, "subnet-b"), vec![0, 1, 2, 3, 4]);
+
+        // A third of four validators reaches the quorum of three at the
+        // lowest index all of them acknowledged.
+        assert_eq!(local.receive_ack(ack(&keys[2], 4), &validators).unwrap(), Some(3));
+        assert_eq!(queued(&local, "subnet-b"), vec![3, 4]);
+
+        // A stale ack never moves a validator's vote back.
+        assert_eq!(local.receive_ack(ack(&keys[2], 1), &validators).unwrap(), None);
+        assert_eq!(local.receive_ack(ack(&keys[3], 5), &validators).unwrap(), Some(4));
+        assert_eq!(queued(&local, "subnet-b"), vec![4]);
+    }
+
+    #[test]
+    fn ack_from_outside_the_validator_set_is_rejected() {
+        let local = streams("subnet-a");
+        let (_, validators) = validators();
+        local.enqueue("subnet-b", "sender".into(), "receiver".into(), "call".into(), vec![]).unwrap();
+
+        let ack = sign_ack("subnet-a", "subnet-b", 1, &signing_key(9)).unwrap();
+        assert!(local.receive_ack(ack, &validators).is_err());
+
+        let mut forged = sign_ack("subnet-a", "subnet-b", 1, &signing_key(1)).unwrap();
+        forged.next_index = 2;
+        assert!(local.receive_ack(forged, &validators).is_err());
+        assert_eq!(queued(&local, "subnet-b"), vec![0]);
+    }
+
+    #[test]
+    fn slice_is_released_once_signatures_reach_a_quorum() {
+        let remote = streams("subnet-b");
+        let local = streams("subnet-a");
+        let (keys, validators) = validators();
+        for _ in 0..3 {
+            remote.enqueue("subnet-a", "sender".into(), "receiver".into(), "call".into(), vec![]).unwrap();
+        }
+        let slice = remote.outgoing_slice("subnet-a").unwrap();
+        let signed_by = |signers: &[SigningKey]| {
+            let mut slice = slice.clone();
+            for key in signers {
+                certify_slice(&mut slice, key).unwrap();
+            }
+            slice
+        };
+
+        assert!(local.receive_slice(signed_by(&keys[..2]), &validators).unwrap().is_empty());
+        assert_eq!(local.next_incoming_index("subnet-b"), 0);
+
+        // Signatures already seen do not count twice; the new one completes
+        // the quorum.
+        let messages = local.receive_slice(signed_by(&keys[1..3]), &validators).unwrap();
+        let indices: Vec<u64> = messages.iter().map(|message| message.message().index).collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+        assert_eq!(local.next_incoming_index("subnet-b"), 3);
+
+        assert!(local.receive_slice(signed_by(&keys), &validators).unwrap().is_empty());
+    }
+
+    #[test]
+    fn slice_with_a_bad_signature_is_rejected() {
+        let remote = streams("subnet-b");
+        let local = streams("subnet-a");
+        let (keys, validators) = validators();
+        remote.enqueue("subnet-a", "sender".into(), "receiver".into(), "call".into(), vec![]).unwrap();
+
+        let mut slice = remote.outgoing_slice("subnet-a").unwrap();
+        for key in &keys[..3] {
+            certify_slice(&mut slice, key).unwrap();
+        }
+        slice.messages[0].payload = vec![1];
+        assert!(local.receive_slice(slice, &validators).is_err());
+
+        let mut outsider = remote.outgoing_slice("subnet-a").unwrap();
+        certify_slice(&mut outsider, &signing_key(9)).unwrap();
+        assert!(local.receive_slice(outsider, &validators).is_err());
+        assert_eq!(local.next_incoming_index("subnet-b"), 0);
+    }
+}
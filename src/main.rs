@@ -4,18 +4,24 @@ use config::Config;
 use rocksdb::DB;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::Mutex;
-use tokio::{io, task};
+use tokio::net::TcpListener;
+use tokio::task;
 
 use crate::core::api::v1::wallet::create_wallet;
 use crate::core::nervous::{create_neuron, NervousSystem};
 use crate::core::staking::{stake, StakingModule};
-use crate::core::consensus::model::ConsensusEngine;
+use crate::core::subnet::SubnetRegistry;
+use crate::core::canister::registry::CanisterRegistry;
+use crate::core::xnet::XNetStreams;
+use crate::core::consensus::mempool::MempoolConfig;
+use crate::core::consensus::model::{ConsensusConfig, ConsensusEngine};
 use crate::core::consensus::consensus::run_consensus_loop;
+use crate::core::consensus::epoch::init_genesis_validator_set;
 use crate::core::consensus::validator::{build_validator, wrap_validator};
 use crate::core::network::machine::{Node, NodeRegistry};
+use crate::core::api::v1::network::{clear_ban, list_bans};
+use crate::core::api::v1::subnet::list_subnets;
+use crate::core::network::{AddressBook, Network, NetworkConfig, NodeIdentity, PeerReputation};
 
 #[tokio::main]
 async fn main() {
@@ -37,11 +43,73 @@ async fn main() {
    let initial_balance: u64 = settings.get(&format!("{}.initial_balance", node_key)).unwrap_or(1000);
    let db_path: String = settings.get(&format!("{}.db_path", node_key)).unwrap_or_else(|_| "nebula_storage".to_string());
    let peer_addresses: Vec<String> = settings.get("network.bootstrap_nodes").unwrap_or_else(|_| vec![]);
+   let chain_id: String = settings.get("network.chain_id").unwrap_or_else(|_| "nebula-local".to_string());
+   let target_peers: usize = settings.get("network.target_peers").unwrap_or(8);
+   let max_messages_per_second: u32 = settings.get("network.max_messages_per_second").unwrap_or(100);
+   let ban_duration_secs: i64 = settings.get("network.ban_duration_secs").unwrap_or(3600);
+   let require_encryption: bool = settings.get("network.require_encryption").unwrap_or(false);
+   let subnet_validators: HashMap<String, Vec<String>> = settings.get("xnet.subnet_validators").unwrap_or_default();
+   let defaults = MempoolConfig::default();
+   let mempool_config = MempoolConfig {
+      max_transactions: settings.get("mempool.max_transactions").unwrap_or(defaults.max_transactions),
+      max_bytes: settings.get("mempool.max_bytes").unwrap_or(defaults.max_bytes),
+      ttl_secs: settings.get("mempool.ttl_secs").unwrap_or(defaults.ttl_secs),
+   };
+   let consensus_config = ConsensusConfig {
+      min_fee: settings.get("consensus.min_fee").unwrap_or(1),
+      fee_burn_percent: settings.get::<u64>("consensus.fee_burn_percent").unwrap_or(0).min(100),
+      max_block_transactions: settings.get("consensus.max_block_transactions").unwrap_or(1000),
+      max_block_bytes: settings.get("consensus.max_block_bytes").unwrap_or(1024 * 1024),
+      epoch_length: settings.get::<u64>("consensus.epoch_length").unwrap_or(100).max(1),
+      min_self_stake: settings.get("consensus.min_self_stake").unwrap_or(100),
+      max_validators: settings.get("consensus.max_validators").unwrap_or(100),
+      liveness_window: settings.get("consensus.liveness_window").unwrap_or(100),
+      max_missed_slots: settings.get::<u64>("consensus.max_missed_slots").unwrap_or(50).max(1),
+      unjail_cooldown: settings.get("consensus.unjail_cooldown").unwrap_or(600),
+      genesis_time: settings.get("consensus.genesis_time").unwrap_or(0),
+      slot_duration_ms: settings.get::<u64>("consensus.slot_duration_ms").unwrap_or(500).max(1),
+      genesis_allocations: settings.get("genesis.allocations").unwrap_or_default(),
+   };
 
    println!("🚀 Starting {} on port {} with initial balance {}", node_name, network_port, initial_balance);
 
    println!("⚡ Initializing RocksDB at {}...", db_path);
    let db = Arc::new(DB::open_default(db_path).expect("Failed to open RocksDB"));
+   let reputation = PeerReputation::new(Arc::clone(&db), ban_duration_secs);
+   let subnet_registry = SubnetRegistry::new(Arc::clone(&db));
+   // Subnet membership only comes from the node sections of the local config,
+   // never from peer gossip.
+   let config_tables: HashMap<String, config::Value> = settings.clone().try_deserialize().unwrap_or_default();
+   for section in config_tables.keys() {
+      if let Ok(member_subnet) = settings.get::<String>(&format!("{}.subnet_id", section)) {
+         subnet_registry.assign_node(&member_subnet, section);
+      }
+   }
+
+   match args.get(2).map(String::as_str) {
+      Some("bans") => {
+         for ban in list_bans(&reputation) {
+            println!("🚫 {} ({}) until {}: {}", ban.ip, ban.node_id, ban.banned_until, ban.reason);
+         }
+         return;
+      }
+      Some("unban") => {
+         let ip = args.get(3).expect("Usage: nebula <node> unban <ip>");
+         if clear_ban(&reputation, ip) {
+            println!("✅ Ban on {} cleared", ip);
+         } else {
+            println!("❌ {} is not banned", ip);
+         }
+         return;
+      }
+      Some("subnets") => {
+         for subnet in list_subnets(&subnet_registry) {
+            println!("🌐 {}: nodes {:?}, canisters {:?}", subnet.subnet_id, subnet.node_ids, subnet.canister_ids);
+         }
+         return;
+      }
+      _ => {}
+   }
 
    let node_registry = NodeRegistry::new(Arc::clone(&db));
    let node = Node {
@@ -55,9 +123,16 @@ async fn main() {
       node_operator_id: settings.get(&format!("{}.node_operator_id", node_key)).unwrap(),
       subnet_id: settings.get(&format!("{}.subnet_id", node_key)).unwrap(),
       ip_address: settings.get(&format!("{}.ip_address", node_key)).unwrap(),
+      port: network_port,
    };
+   let public_address = format!("{}:{}", node.ip_address, node.port);
+   let subnet_id = node.subnet_id.clone();
    node_registry.register_node(node);
 
+   println!("⚡ Loading node identity...");
+   let node_identity = NodeIdentity::new(Arc::clone(&db));
+   println!("✅ Node identity: {}", node_identity.node_id);
+
    println!("⚡ Creating wallet...");
    let wallet = create_wallet(Arc::clone(&db));
    println!("✅ Wallet created with address: {}", wallet.address);
@@ -86,11 +161,14 @@ async fn main() {
    println!("✅ Validators wrapped: {:?}", validators.lock());
 
    println!("⚡ Initializing Consensus Engine...");
-   let mut consensus_engine = ConsensusEngine::new(validators, nervous_system.neurons.clone(), Arc::clone(&db));
+   let mut consensus_engine = ConsensusEngine::new(subnet_id.clone(), consensus_config, mempool_config, validators, nervous_system.neurons.clone(), Arc::clone(&db));
    println!("✅ Consensus Engine initialized with validators: {:?}", consensus_engine.validators.lock());
 
    println!("⚡ Initializing ledger...");
-   consensus_engine.init_ledger(wallet.address.clone(), wallet.public_key, initial_balance);
+   consensus_engine.init_genesis_ledger().expect("Invalid genesis allocation");
+   if !consensus_engine.config.genesis_allocations.contains_key(&wallet.address) {
+      consensus_engine.init_ledger(wallet.address.clone(), wallet.public_key, initial_balance);
+   }
    println!("✅ Ledger initialized.");
 
    println!("⚡ Staking tokens...");
@@ -105,9 +183,21 @@ async fn main() {
        .expect("Failed to stake tokens");
    println!("✅ Staking complete.");
 
+   init_genesis_validator_set(&consensus_engine);
+
+   println!("⚡ Initializing XNet streams...");
+   let local_validators = consensus_engine.validators.lock().iter().map(|v| v.address.clone()).collect();
+   subnet_registry.set_validators(&subnet_id, local_validators);
+   for (remote_subnet, validators) in subnet_validators {
+      if remote_subnet != subnet_id {
+         subnet_registry.set_validators(&remote_subnet, validators);
+      }
+   }
+   let canister_registry = CanisterRegistry::new(subnet_id.clone(), subnet_registry.clone(), Arc::clone(&db));
+   let xnet_streams = XNetStreams::new(subnet_id.clone(), canister_registry, Arc::clone(&db));
+   println!("✅ XNet streams initialized.");
+
    let mut consensus_engine_clone = consensus_engine.clone();
-   let mut staking_module_clone = staking_module.clone();
-   let mut nervous_system_clone = nervous_system.clone();
    let signing_key_clone = wallet.signing_key.clone();
 
    println!("⚡ Binding TCP Listener on port {}...", network_port);
@@ -116,34 +206,51 @@ async fn main() {
        .expect("Failed to bind TCP listener");
    println!("✅ Listening for peer connections on port {}", network_port);
 
+   let network_config = NetworkConfig {
+      chain_id,
+      listen_port: network_port,
+      public_address,
+      local_node_id: node_key.clone(),
+      target_peers,
+      max_messages_per_second,
+      require_encryption,
+   };
+   let address_book = AddressBook::new(Arc::clone(&db));
+   let network = Network::new(consensus_engine.clone(), node_registry.clone(), subnet_registry.clone(), xnet_streams, address_book, reputation, node_identity, network_config);
+   let network_clone = network.clone();
+
    tokio::spawn(async move {
       println!("⚡ Starting consensus loop...");
-      run_consensus_loop(&mut nervous_system_clone, &mut consensus_engine_clone, &mut staking_module_clone, &signing_key_clone).await;
+      run_consensus_loop(&mut consensus_engine_clone, &signing_key_clone, &network_clone).await;
       println!("✅ Consensus loop started.");
    });
 
-   let peers = Arc::new(Mutex::new(HashMap::new()));
-
-   println!("⚡ Connecting to peers...");
-   for peer in peer_addresses {
-      if let Ok(stream) = TcpStream::connect(peer.clone()).await {
-         println!("✅ Connected to peer {}", peer);
-         let peers_clone = Arc::clone(&peers);
-         tokio::spawn(async move {
-            let _ = handle_connection(stream, peers_clone).await;
-         });
-      } else {
-         println!("❌ Failed to connect to peer {}", peer);
-      }
-   }
+   println!("⚡ Seeding peer address book...");
+   network.seed_address_book(peer_addresses);
+
+   let network_clone = network.clone();
+   tokio::spawn(async move {
+      network_clone.maintain_peers().await;
+   });
+
+   let network_clone = network.clone();
+   tokio::spawn(async move {
+      network_clone.run_sync_loop().await;
+   });
+
+   let network_clone = network.clone();
+   let signing_key_clone = wallet.signing_key.clone();
+   tokio::spawn(async move {
+      network_clone.run_xnet_loop(signing_key_clone).await;
+   });
 
    loop {
       match listener.accept().await {
          Ok((stream, addr)) => {
             println!("🔗 New connection from {}", addr);
-            let peers_clone = Arc::clone(&peers);
+            let network_clone = network.clone();
             task::spawn(async move {
-               if let Err(e) = handle_connection(stream, peers_clone).await {
+               if let Err(e) = network_clone.handle_connection(stream).await {
                   eprintln!("❌ Error handling connection from {}: {}", addr, e);
                }
             });
@@ -152,48 +259,3 @@ async fn main() {
       }
    }
 }
-
-async fn handle_connection(
-   stream: TcpStream,
-   peers: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
-) -> io::Result<()> {
-   let addr = stream.peer_addr()?.to_string();
-   let stream = Arc::new(Mutex::new(stream));
-   {
-      let mut peers_map = peers.lock().await;
-      peers_map.insert(addr.clone(), Arc::clone(&stream));
-   }
-   println!("🔗 Peer connected: {}", addr);
-   let mut buffer = vec![0u8; 1024];
-   loop {
-      let n = {
-         let mut locked_stream = stream.lock().await;
-         match locked_stream.read(&mut buffer).await {
-            Ok(n) => n,
-            Err(e) => {
-               eprintln!("❌ Error reading from peer {}: {}", addr, e);
-               break;
-            }
-         }
-      };
-      if n == 0 {
-         println!("🔌 Peer {} disconnected", addr);
-         break;
-      }
-      let message = String::from_utf8_lossy(&buffer[..n]);
-      println!("📩 Message from {}: {}", addr, message);
-      {
-         let mut locked_stream = stream.lock().await;
-         if let Err(e) = locked_stream.write_all(b"ACK").await {
-            eprintln!("❌ Error writing to peer {}: {}", addr, e);
-            break;
-         }
-      }
-   }
-   {
-      let mut peers_map = peers.lock().await;
-      peers_map.remove(&addr);
-   }
-   println!("🔌 Connection closed with peer: {}", addr);
-   Ok(())
-}